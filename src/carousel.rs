@@ -0,0 +1,141 @@
+use crate::{ArcCell, ArcRef};
+
+/// A cell that switches between `N` preallocated values.
+///
+/// All boxes are allocated once, in [`new`](Carousel::new), and
+/// [`select`](Carousel::select) only changes which one is installed, like
+/// [`Flip`](crate::Flip) with more slots.
+pub struct Carousel<T, const N: usize> {
+    cell: ArcCell<T>,
+    slots: [*mut T; N],
+}
+
+unsafe impl<T: Sync + Send, const N: usize> Send for Carousel<T, N> {}
+unsafe impl<T: Sync + Send, const N: usize> Sync for Carousel<T, N> {}
+
+impl<T, const N: usize> Carousel<T, N> {
+    /// Creates a carousel with slot `0` installed
+    ///
+    /// Panics if `N` is zero.
+    #[inline]
+    pub fn new(values: [Box<T>; N]) -> Carousel<T, N> {
+        assert!(N > 0, "a carousel needs at least one slot");
+        let slots = values.map(Box::into_raw);
+
+        Self {
+            cell: ArcCell::new(unsafe { Box::from_raw(slots[0]) }),
+            slots,
+        }
+    }
+
+    #[inline]
+    pub fn get(&self) -> ArcRef<T> {
+        self.cell.get()
+    }
+
+    /// Installs slot `idx` and returns a reference to it
+    ///
+    /// Panics if `idx` is out of bounds.
+    #[inline]
+    pub fn select(&self, idx: usize) -> ArcRef<T> {
+        assert!(idx < N, "slot {} out of bounds for a carousel of {}", idx, N);
+        self.cell.inner().set_ptr(self.slots[idx]);
+        self.cell.get()
+    }
+}
+
+impl<T, const N: usize> Drop for Carousel<T, N> {
+    fn drop(&mut self) {
+        // The cell owns whichever value is installed; the rest are ours.
+        // Zero-sized values can all share one address, so skip exactly one
+        // matching slot rather than every slot at that address. Readers can
+        // outlive the carousel and may be looking at any slot, so the cell
+        // frees them once they are gone.
+        let current = self.cell.inner().ptr() as *mut T;
+        let installed = self.slots.iter().position(|&slot| slot == current);
+
+        for (i, &slot) in self.slots.iter().enumerate() {
+            if Some(i) != installed {
+                self.cell.inner().retire(slot, None);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn select_cycles_slots() {
+        let carousel = Arc::new(Carousel::new([
+            Box::new(0),
+            Box::new(1),
+            Box::new(2),
+            Box::new(3),
+        ]));
+
+        let readers = (0..4)
+            .map(|_| {
+                let carousel = Arc::clone(&carousel);
+                std::thread::spawn(move || {
+                    for _ in 0..1000 {
+                        assert!(*carousel.get() < 4);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for i in 0..1000 {
+            assert_eq!(*carousel.select(i % 4), i % 4);
+        }
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn select_out_of_bounds() {
+        Carousel::new([Box::new(0), Box::new(1)]).select(2);
+    }
+
+    #[test]
+    fn zero_sized_values_dropped_once_each() {
+        use std::cell::Cell;
+
+        thread_local! {
+            static DROPS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        struct Unit;
+
+        impl Drop for Unit {
+            fn drop(&mut self) {
+                DROPS.with(|n| n.set(n.get() + 1));
+            }
+        }
+
+        let carousel = Carousel::new([Box::new(Unit), Box::new(Unit), Box::new(Unit)]);
+        carousel.select(2);
+        drop(carousel);
+
+        assert_eq!(DROPS.with(Cell::get), 3);
+    }
+
+    #[test]
+    fn slots_outlive_carousel_while_read() {
+        let first = Arc::new(0);
+        let carousel = Carousel::new([Box::new(Arc::clone(&first)), Box::new(Arc::new(1))]);
+
+        let reader = carousel.get();
+        carousel.select(1);
+        drop(carousel);
+        assert_eq!(Arc::strong_count(&first), 2);
+
+        drop(reader);
+        assert_eq!(Arc::strong_count(&first), 1);
+    }
+}
@@ -0,0 +1,189 @@
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::{
+    atomic::{AtomicPtr, AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::ArcCell;
+
+/// Fixed-size single-producer single-consumer ring of `(position, value)`
+/// entries. When full, the producer overwrites the oldest entry.
+struct Ring<T> {
+    slots: Box<[AtomicPtr<(u64, T)>]>,
+    /// Number of entries ever pushed
+    write: AtomicU64,
+    phantom: PhantomData<Box<T>>,
+}
+
+impl<T> Ring<T> {
+    fn new(capacity: usize) -> Ring<T> {
+        assert!(capacity > 0, "a change stream needs room for at least one change");
+
+        Self {
+            slots: (0..capacity).map(|_| AtomicPtr::new(ptr::null_mut())).collect(),
+            write: AtomicU64::new(0),
+            phantom: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn slot(&self, position: u64) -> &AtomicPtr<(u64, T)> {
+        &self.slots[(position % self.slots.len() as u64) as usize]
+    }
+
+    /// Only called by the producer
+    fn push(&self, value: T) {
+        let position = self.write.load(Ordering::Relaxed);
+        let entry = Box::into_raw(Box::new((position, value)));
+        let overwritten = self.slot(position).swap(entry, Ordering::AcqRel);
+
+        if !overwritten.is_null() {
+            drop(unsafe { Box::from_raw(overwritten) });
+        }
+
+        self.write.store(position + 1, Ordering::Release);
+    }
+
+    /// Only called by the consumer, whose next position is `read`
+    fn pop(&self, read: &mut u64) -> Option<T> {
+        let capacity = self.slots.len() as u64;
+
+        loop {
+            let write = self.write.load(Ordering::Acquire);
+
+            if *read == write {
+                return None;
+            }
+
+            if write - *read > capacity {
+                *read = write - capacity;
+            }
+
+            let slot = self.slot(*read);
+            let entry = slot.swap(ptr::null_mut(), Ordering::AcqRel);
+
+            if entry.is_null() {
+                continue;
+            }
+
+            if unsafe { (*entry).0 } == *read {
+                *read += 1;
+                return Some(unsafe { Box::from_raw(entry) }.1);
+            }
+
+            // The producer lapped us between the load and the swap, so this is
+            // a newer entry. Put it back for when we get to its position.
+            if slot
+                .compare_exchange(ptr::null_mut(), entry, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+            {
+                drop(unsafe { Box::from_raw(entry) });
+            }
+        }
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        for slot in self.slots.iter_mut() {
+            let entry = *slot.get_mut();
+
+            if !entry.is_null() {
+                drop(unsafe { Box::from_raw(entry) });
+            }
+        }
+    }
+}
+
+/// Write half of a [`change_stream`](ArcCell::change_stream)
+pub struct ChangeProducer<T> {
+    cell: ArcCell<T>,
+    ring: Arc<Ring<T>>,
+}
+
+/// Read half of a [`change_stream`](ArcCell::change_stream)
+pub struct ChangeConsumer<T> {
+    ring: Arc<Ring<T>>,
+    read: u64,
+}
+
+impl<T: Clone> ChangeProducer<T> {
+    /// Installs `data` in the cell, queues a copy for the consumer and
+    /// returns the old value
+    #[inline]
+    pub fn set(&mut self, data: Box<T>) -> Box<T> {
+        self.ring.push(T::clone(&data));
+        self.cell.set(data)
+    }
+}
+
+impl<T> ChangeConsumer<T> {
+    /// Takes the oldest queued change. Changes pushed out by a full ring are
+    /// skipped.
+    #[inline]
+    pub fn next_change(&mut self) -> Option<T> {
+        self.ring.pop(&mut self.read)
+    }
+}
+
+impl<T> ArcCell<T> {
+    /// Creates a bounded stream of the values installed through the returned
+    /// [`ChangeProducer`], holding at most `capacity` changes before the
+    /// oldest are dropped. Writes made directly on the cell are not queued.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn change_stream(&self, capacity: usize) -> (ChangeProducer<T>, ChangeConsumer<T>) {
+        let ring = Arc::new(Ring::new(capacity));
+
+        (
+            ChangeProducer { cell: self.clone(), ring: Arc::clone(&ring) },
+            ChangeConsumer { ring, read: 0 },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflow_drops_oldest() {
+        let v = ArcCell::new(Box::new(0));
+        let (mut producer, mut consumer) = v.change_stream(4);
+
+        for i in 1..=10 {
+            producer.set(Box::new(i));
+        }
+        assert_eq!(*v.get(), 10);
+
+        let changes = std::iter::from_fn(|| consumer.next_change()).collect::<Vec<_>>();
+        assert_eq!(changes, vec![7, 8, 9, 10]);
+
+        producer.set(Box::new(11));
+        assert_eq!(consumer.next_change(), Some(11));
+        assert_eq!(consumer.next_change(), None);
+    }
+
+    #[test]
+    fn concurrent_changes_in_order() {
+        let v = ArcCell::new(Box::new(0u64));
+        let (mut producer, mut consumer) = v.change_stream(8);
+
+        let writer = std::thread::spawn(move || {
+            for i in 1..=10_000 {
+                producer.set(Box::new(i));
+            }
+        });
+
+        let mut last = 0;
+        while last < 10_000 {
+            if let Some(value) = consumer.next_change() {
+                assert!(value > last);
+                last = value;
+            }
+        }
+
+        writer.join().unwrap();
+    }
+}
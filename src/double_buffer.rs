@@ -0,0 +1,130 @@
+use std::ptr::NonNull;
+
+use crate::{ArcCell, ArcCellInner, ArcRef, Subscription};
+
+/// A front buffer shared with readers and a back buffer owned by the writer.
+///
+/// The writer fills the back buffer through [`back_mut`](Self::back_mut) and
+/// publishes it with [`swap_buffers`](Self::swap_buffers), which installs it
+/// as the front in one atomic step and takes the old front back as the new
+/// back buffer. Both allocations are reused forever.
+///
+/// Readers may still be looking at the old front just after a swap, so the
+/// back buffer only becomes writable again once no reader is live.
+pub struct DoubleBuffer<T> {
+    front: ArcCell<T>,
+    /// Owned like a box, but readers may still hold references into it while
+    /// `back_shared` is set, so it is only made `&mut` in `back_mut`
+    back: NonNull<T>,
+    /// Whether the back buffer was the front since readers last drained
+    back_shared: bool,
+}
+
+unsafe impl<T: Sync + Send> Send for DoubleBuffer<T> {}
+unsafe impl<T: Sync + Send> Sync for DoubleBuffer<T> {}
+
+impl<T> DoubleBuffer<T> {
+    #[inline]
+    pub fn new(front: Box<T>, back: Box<T>) -> DoubleBuffer<T> {
+        Self {
+            front: ArcCell::new(front),
+            back: unsafe { NonNull::new_unchecked(Box::into_raw(back)) },
+            back_shared: false,
+        }
+    }
+
+    #[inline]
+    pub fn front(&self) -> ArcRef<T> {
+        self.front.get()
+    }
+
+    /// A read-only handle to the front buffer for readers on other threads
+    #[inline]
+    pub fn subscribe(&self) -> Subscription<T> {
+        self.front.subscribe()
+    }
+
+    /// The back buffer, or `None` while a reader that might be looking at it
+    /// from before the last swap is still live
+    #[inline]
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        if self.back_shared {
+            if self.front.reader_count() != 0 {
+                return None;
+            }
+
+            self.back_shared = false;
+        }
+
+        Some(unsafe { self.back.as_mut() })
+    }
+
+    /// Publishes the back buffer as the front. Readers holding an `ArcRef`
+    /// to the front see the new front from then on.
+    #[inline]
+    pub fn swap_buffers(&mut self) {
+        let old = self.front.inner().set_ptr(self.back.as_ptr());
+
+        self.back = unsafe { NonNull::new_unchecked(ArcCellInner::ptr_of(old)) };
+        self.back_shared = true;
+    }
+}
+
+impl<T> Drop for DoubleBuffer<T> {
+    fn drop(&mut self) {
+        // Readers can outlive the buffers and may still be looking at the
+        // back one, so the cell frees it once they are gone
+        self.front.inner().retire(self.back.as_ptr(), None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn reader_survives_swap() {
+        let mut buffers = DoubleBuffer::new(Box::new(vec![0u8; 4]), Box::new(vec![0u8; 4]));
+        let front = buffers.front();
+
+        buffers.back_mut().unwrap().fill(1);
+        buffers.swap_buffers();
+        assert_eq!(*front, vec![1; 4]);
+        drop(front);
+
+        buffers.back_mut().unwrap().fill(2);
+        let front = buffers.front();
+        assert_eq!(*front, vec![1; 4]);
+
+        buffers.swap_buffers();
+        assert_eq!(*front, vec![2; 4]);
+        drop(front);
+        assert_eq!(*buffers.back_mut().unwrap(), vec![1; 4]);
+    }
+
+    #[test]
+    fn back_waits_for_readers() {
+        let mut buffers = DoubleBuffer::new(Box::new(vec![1u8; 4]), Box::new(vec![0u8; 4]));
+        let subscription = buffers.subscribe();
+        let (read_tx, read_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+
+        let reader = std::thread::spawn(move || {
+            let front = subscription.latest().unwrap();
+            let seen: &Vec<u8> = &front;
+            read_tx.send(()).unwrap();
+
+            done_rx.recv().unwrap();
+            assert_eq!(*seen, vec![1; 4]);
+        });
+
+        read_rx.recv().unwrap();
+        buffers.swap_buffers();
+        assert!(buffers.back_mut().is_none());
+
+        done_tx.send(()).unwrap();
+        reader.join().unwrap();
+        assert_eq!(*buffers.back_mut().unwrap(), vec![1; 4]);
+    }
+}
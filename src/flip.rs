@@ -0,0 +1,107 @@
+use crate::{ArcCell, ArcRef};
+
+/// A cell that toggles between two preallocated values.
+///
+/// Both boxes are allocated once, in [`new`](Flip::new), and
+/// [`flip`](Flip::flip) only ever swaps which one is installed, so flipping
+/// never allocates or frees.
+pub struct Flip<T> {
+    cell: ArcCell<T>,
+    a: *mut T,
+    b: *mut T,
+}
+
+unsafe impl<T: Sync + Send> Send for Flip<T> {}
+unsafe impl<T: Sync + Send> Sync for Flip<T> {}
+
+impl<T> Flip<T> {
+    /// Creates a flip with `a` installed
+    #[inline]
+    pub fn new(a: Box<T>, b: Box<T>) -> Flip<T> {
+        let a = Box::into_raw(a);
+
+        Self {
+            cell: ArcCell::new(unsafe { Box::from_raw(a) }),
+            a,
+            b: Box::into_raw(b),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self) -> ArcRef<T> {
+        self.cell.get()
+    }
+
+    /// Installs whichever value is not currently installed and returns a
+    /// reference to it
+    pub fn flip(&self) -> ArcRef<T> {
+        let mut current = self.cell.inner().ptr() as *mut T;
+
+        loop {
+            let other = if current == self.a { self.b } else { self.a };
+
+            match self.cell.inner().compare_exchange_ptr(current, other) {
+                Ok(()) => return self.cell.get(),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl<T> Drop for Flip<T> {
+    fn drop(&mut self) {
+        // The cell owns whichever value is installed; the other one is ours.
+        // Zero-sized values can share an address, in which case `a` counts
+        // as installed and `b` is still dropped exactly once. Readers can
+        // outlive the flip and may be looking at the spare, so the cell
+        // frees it once they are gone.
+        let current = self.cell.inner().ptr() as *mut T;
+        let spare = if current == self.a { self.b } else { self.a };
+
+        self.cell.inner().retire(spare, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::sync::Arc;
+
+    #[test]
+    fn zero_sized_values_dropped_once_each() {
+        thread_local! {
+            static DROPS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        struct Unit;
+
+        impl Drop for Unit {
+            fn drop(&mut self) {
+                DROPS.with(|n| n.set(n.get() + 1));
+            }
+        }
+
+        let flip = Flip::new(Box::new(Unit), Box::new(Unit));
+        flip.flip();
+        flip.flip();
+        flip.flip();
+        drop(flip);
+
+        assert_eq!(DROPS.with(Cell::get), 2);
+    }
+
+    #[test]
+    fn spare_outlives_flip_while_read() {
+        let a = Arc::new(0);
+        let flip = Flip::new(Box::new(Arc::clone(&a)), Box::new(Arc::new(1)));
+
+        let reader = flip.get();
+        flip.flip();
+        drop(flip);
+        assert_eq!(Arc::strong_count(&a), 2);
+
+        drop(reader);
+        assert_eq!(Arc::strong_count(&a), 1);
+    }
+}
@@ -1,233 +1,2200 @@
 #![feature(integer_atomics)]
 
 use std::sync::{
-    atomic::{AtomicU128, Ordering},
+    atomic::{AtomicPtr, AtomicU128, AtomicU64, Ordering},
+    mpsc, Arc, Mutex, MutexGuard, Weak,
 };
-use std::{marker::PhantomData, ptr::NonNull, fmt::{Debug, Display}, ops::Deref};
+use std::{marker::PhantomData, mem::MaybeUninit, ptr::NonNull, fmt::{Debug, Display}, ops::{Deref, DerefMut}};
+use std::any::Any;
+use std::collections::{hash_map::DefaultHasher, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io::IoSlice;
+use std::iter::FromIterator;
+use std::time::{Duration, Instant};
+#[cfg(feature = "async")]
+use std::{future::Future, pin::Pin, task::{Context, Poll}};
+
+mod carousel;
+mod change_stream;
+mod double_buffer;
+mod flip;
+mod list;
+mod pool;
+mod reclaim;
+#[cfg(feature = "sha2")]
+mod sha256;
+
+pub use carousel::Carousel;
+pub use change_stream::{ChangeConsumer, ChangeProducer};
+pub use double_buffer::DoubleBuffer;
+pub use flip::Flip;
+pub use list::Node;
+pub use pool::{Pool, PooledCell};
+pub use reclaim::BackgroundCell;
+#[cfg(feature = "sha2")]
+use sha256::Sha256;
 
 
-#[repr(transparent)]
 pub struct ArcRef<T> {
     ptr: NonNull<ArcCellInner<T>>,
+    /// The cell's version when the reader was taken
+    version: u64,
     phantom: PhantomData<ArcCellInner<T>>,
 }
 
+/// A reference to part of a cell's value, see [`ArcRef::map`]. Like
+/// `ArcRef`, it projects from whatever the cell holds each time it is read.
+pub struct MappedRef<T, U: ?Sized> {
+    base: ArcRef<T>,
+    project: Box<dyn for<'a> Fn(&'a T) -> &'a U>,
+}
+
+/// A type-erased reader, see [`ArcCell::erase`]
+pub struct ErasedRef {
+    inner: Box<dyn ErasedRead>,
+}
+
+/// An `ArcRef` with its value type hidden
+trait ErasedRead {
+    fn value(&self) -> &(dyn Any + Send + Sync);
+}
+
+impl<T: Any + Send + Sync> ErasedRead for ArcRef<T> {
+    #[inline]
+    fn value(&self) -> &(dyn Any + Send + Sync) {
+        &**self
+    }
+}
+
 pub struct ArcCell<T> {
     ptr: NonNull<ArcCellInner<T>>,
     phantom: PhantomData<ArcCellInner<T>>,
 }
 
-#[repr(transparent)]
-/// (strong: u64, ptr: u64)
-struct ArcCellInner<T: ?Sized>(AtomicU128, PhantomData<T>);
+/// A handle that keeps the inner node allocated without keeping the cell alive
+pub struct WeakCell<T> {
+    ptr: NonNull<ArcCellInner<T>>,
+    phantom: PhantomData<ArcCellInner<T>>,
+}
+
+/// A pending write from [`ArcCell::set_with_rollback`]. Dropping it commits.
+pub struct RollbackToken<'a, T> {
+    cell: &'a ArcCell<T>,
+    version: u64,
+    /// `None` once rolled back
+    old: Option<Box<T>>,
+}
+
+/// In-place access to a uniquely held value, see [`ArcCell::edit`]. Dropping
+/// it commits.
+pub struct Editor<'a, T> {
+    cell: &'a ArcCell<T>,
+    value: &'a mut T,
+    /// `None` once the edit has been aborted
+    saved: Option<T>,
+}
+
+/// A shared batch number for stamping writes across cells, see
+/// [`ArcCell::update_tracked`]
+#[derive(Debug, Default)]
+pub struct Generation(AtomicU64);
+
+/// The cell's value and counters at one point in time, see [`ArcCell::full_snapshot`]
+pub struct FullSnapshot<T> {
+    value: ArcRef<T>,
+    strong: u64,
+    readers: u64,
+    version: u64,
+    last_set: Instant,
+}
+
+/// An intent to write, see [`ArcCell::reserve`]
+pub struct Reservation<'a, T> {
+    cell: &'a ArcCell<T>,
+    version: u64,
+}
+
+/// Resolves to the cell once it has changed, see [`ArcCell::get_and_watch`]
+#[cfg(feature = "async")]
+pub struct ChangeFuture<T> {
+    inner: Pin<Box<dyn Future<Output = ArcRef<T>> + Send>>,
+}
+
+/// An optimistic write, see [`ArcCell::begin_write`]
+pub struct WriteTicket<'a, T> {
+    cell: &'a ArcCell<T>,
+    version: u64,
+}
+
+/// A pull-based observer of a cell, see [`ArcCell::subscribe`]
+pub struct Subscription<T> {
+    cell: WeakCell<T>,
+}
+
+/// A value computed from a cell and cached per version, see [`ArcCell::derived`]
+pub struct Derived<T, U> {
+    cell: ArcCell<T>,
+    f: Box<dyn Fn(&T) -> U + Send + Sync>,
+    /// The last result, with the version it was computed at
+    cached: Mutex<Option<(u64, U)>>,
+}
+
+/// A value swapped out by one of the cell's own writes, kept until no reader
+/// can still be looking at it
+struct Retired<T> {
+    value: *mut T,
+    /// How to free `value` if it is not simply a `Box<T>` to drop
+    free: Option<unsafe fn(*mut T)>,
+    next: *mut Retired<T>,
+}
+
+/// State used by only some of the cell's methods, allocated by the first of
+/// them to run so that other cells don't carry it
+struct Extras<T> {
+    /// (valid: u1, version: u24, hash: u64) of the last `set_hashed`
+    hash: AtomicU128,
+    /// The last `Arc` handed out by `shared_snapshot`, with the version it copies
+    shared: Mutex<(u64, Weak<T>)>,
+    /// Fingerprints of the most recent `set_fingerprinted` values, newest last
+    fingerprints: Mutex<VecDeque<u64>>,
+    /// Values displaced by `set_undoable`, newest last, each with the version
+    /// the cell must still be at for `undo` to restore it
+    undo: Mutex<VecDeque<(u64, Box<T>)>>,
+    /// The `Generation` of the last `update_tracked`
+    generation: AtomicU64,
+    /// Woken by writes while `watchers` is non-zero, for `get_and_watch`
+    #[cfg(feature = "async")]
+    changed: tokio::sync::Notify,
+    /// Number of `ChangeFuture`s that have been polled and not finished
+    #[cfg(feature = "async")]
+    watchers: AtomicU64,
+}
+
+/// Counts a `ChangeFuture` among the watchers until it finishes or is dropped
+#[cfg(feature = "async")]
+struct Watching<'a>(&'a AtomicU64);
+
+#[repr(C)]
+struct ArcCellInner<T> {
+    /// (dirty: u1, strong: u23, readers: u24, version: u24, ptr: u56)
+    ///
+    /// The value pointer is stored as its low 48 bits, which cover the
+    /// user-space address range on x86_64 and aarch64, followed by its top
+    /// byte, which holds the tag on aarch64 with top-byte-ignore (Android,
+    /// MTE, HWASan). Bits 48 to 55 are not stored, so `new` panics on
+    /// pointers above the 48-bit range, as from 5-level paging on x86_64 or
+    /// 52-bit addressing on aarch64.
+    state: AtomicU128,
+    /// Weak handles, plus one shared by all strong handles
+    weak: AtomicU64,
+    created: Instant,
+    /// Nanoseconds after `created` of the last write
+    last_set: AtomicU64,
+    /// Values displaced by the cell's own writes, newest first, see `retire`
+    retired: AtomicPtr<Retired<T>>,
+    /// Null until first needed, see `extras`
+    extras: AtomicPtr<Extras<T>>,
+    #[cfg(debug_assertions)]
+    canary: u64,
+    phantom: PhantomData<T>,
+}
 
 unsafe impl<T: Sync + Send> Send for ArcCell<T> {}
 unsafe impl<T: Sync + Send> Sync for ArcCell<T> {}
+unsafe impl<T: Sync + Send> Send for WeakCell<T> {}
+unsafe impl<T: Sync + Send> Sync for WeakCell<T> {}
 
 impl<T> ArcCellInner<T> {
-    const MASK_STRONG: u128 = 0xFFFF_FFFF_FFFF_FFFF_0000_0000_0000_0000;
-    const MASK_PTR: u128 = 0x0000_0000_0000_0000_FFFF_FFFF_FFFF_FFFF;
+    const MASK_DIRTY: u128 = 0x8000_0000_0000_0000_0000_0000_0000_0000;
+    const MASK_STRONG: u128 = 0x7FFF_FF00_0000_0000_0000_0000_0000_0000;
+    const MASK_READERS: u128 = 0x0000_00FF_FFFF_0000_0000_0000_0000_0000;
+    const MASK_VERSION: u128 = 0x0000_0000_0000_FFFF_FF00_0000_0000_0000;
+    const MASK_PTR: u128 = 0x0000_0000_0000_0000_00FF_FFFF_FFFF_FFFF;
+
+    const SHIFT_STRONG: u32 = 104;
+    const SHIFT_READERS: u32 = 80;
+    const SHIFT_VERSION: u32 = 56;
 
-    const ONE_STRONG: u128 = 1 << 64;
+    /// The pointer bits stored where they are; the top byte is stored just
+    /// above them
+    const PTR_ADDRESS: u128 = 0x0000_FFFF_FFFF_FFFF;
+    const SHIFT_TAG: u32 = 56;
+
+    const ONE_STRONG: u128 = 1 << Self::SHIFT_STRONG;
+    const ONE_READER: u128 = 1 << Self::SHIFT_READERS;
+    const ONE_VERSION: u128 = 1 << Self::SHIFT_VERSION;
+
+    /// Counts past which an increment aborts rather than risk carrying into
+    /// the next field up. Like `Arc`'s limit these sit well below the field
+    /// width, leaving room for threads that increment before any of them sees
+    /// the limit.
+    const MAX_STRONG: u64 = 1 << 22;
+    const MAX_READERS: u64 = 1 << 23;
+
+    /// How many fingerprints `set_fingerprinted` remembers
+    const RECENT_FINGERPRINTS: usize = 16;
+
+    #[cfg(debug_assertions)]
+    const CANARY: u64 = 0x0A4C_CE11_0A4C_CE11;
+    #[cfg(debug_assertions)]
+    const CANARY_FREED: u64 = 0xDEAD_CE11_DEAD_CE11;
+
+    /// Panics if the canary no longer holds its magic value, which means the
+    /// node was freed (or otherwise clobbered) before this access.
+    #[cfg(debug_assertions)]
+    #[inline(always)]
+    fn check_canary(&self) {
+        let canary = unsafe { std::ptr::read_volatile(&self.canary) };
+
+        if canary != Self::CANARY {
+            panic!(
+                "ArcCell canary is {:#x}, expected {:#x}: a freed ArcCellInner was dereferenced",
+                canary,
+                Self::CANARY
+            );
+        }
+    }
 
     #[inline(always)]
     fn strong_count(&self) -> u64 {
-        (self.0.load(Ordering::Acquire) & Self::MASK_STRONG >> 64) as u64
+        Self::strong_of(self.state.load(Ordering::Acquire))
+    }
+
+    #[inline(always)]
+    fn reader_count(&self) -> u64 {
+        Self::readers_of(self.state.load(Ordering::Acquire))
+    }
+
+    #[inline(always)]
+    fn version(&self) -> u64 {
+        self.snapshot().0
     }
 
     #[inline(always)]
     fn ptr(&self) -> *const T {
-        (self.0.load(Ordering::Acquire) & Self::MASK_PTR) as *const T
+        Self::ptr_of(self.state.load(Ordering::Acquire))
     }
 
+    /// Reads the version and pointer from a single load, so they always agree
     #[inline(always)]
-    fn set_ptr_null(&self) -> (u64, *mut T) {
-        loop {
-            let current = self.0.load(Ordering::Relaxed);
-            let new = current & !Self::MASK_PTR;
+    fn snapshot(&self) -> (u64, *const T) {
+        let value = self.state.load(Ordering::Acquire);
 
-            if let Ok(value) =
-                self.0
-                    .compare_exchange(current, new, Ordering::Release, Ordering::Relaxed)
-            {
-                let strong = (value & Self::MASK_STRONG >> 64) as u64;
-                let ptr = (value & Self::MASK_PTR) as *mut T;
+        (Self::version_of(value), Self::ptr_of(value))
+    }
 
-                return (strong, ptr);
-            }
-        }
+    #[inline(always)]
+    fn strong_of(value: u128) -> u64 {
+        ((value & Self::MASK_STRONG) >> Self::SHIFT_STRONG) as u64
+    }
+
+    #[inline(always)]
+    fn readers_of(value: u128) -> u64 {
+        ((value & Self::MASK_READERS) >> Self::SHIFT_READERS) as u64
+    }
+
+    #[inline(always)]
+    fn version_of(value: u128) -> u64 {
+        ((value & Self::MASK_VERSION) >> Self::SHIFT_VERSION) as u64
+    }
+
+    /// The version after `version`, wrapping like the state word does
+    #[inline(always)]
+    fn next_version(version: u64) -> u64 {
+        (version + 1) & (Self::MASK_VERSION >> Self::SHIFT_VERSION) as u64
+    }
+
+    #[inline(always)]
+    fn ptr_of(value: u128) -> *mut T {
+        let packed = value & Self::MASK_PTR;
+
+        ((packed & Self::PTR_ADDRESS) | (packed >> 48 << Self::SHIFT_TAG)) as *mut T
+    }
+
+    #[inline(always)]
+    fn pack_ptr(ptr: *const T) -> u128 {
+        let ptr = ptr as u128;
+        assert!(
+            ptr & !(Self::PTR_ADDRESS | 0xFF << Self::SHIFT_TAG) == 0,
+            "ArcCell value pointer {:#x} uses bits 48 to 55, which are not stored",
+            ptr
+        );
+
+        (ptr & Self::PTR_ADDRESS) | (ptr >> Self::SHIFT_TAG << 48)
+    }
+
+    /// Replaces the pointer in `value` with `ptr`, bumping the version and
+    /// setting the dirty bit
+    #[inline(always)]
+    fn with_ptr(value: u128, ptr: *mut T) -> u128 {
+        let version = (value & Self::MASK_VERSION).wrapping_add(Self::ONE_VERSION) & Self::MASK_VERSION;
+
+        (value & (Self::MASK_STRONG | Self::MASK_READERS)) | Self::MASK_DIRTY | version | Self::pack_ptr(ptr)
     }
 
     #[inline(always)]
     fn new(ptr: *const T) -> ArcCellInner<T> {
-        let start = Self::ONE_STRONG | (ptr as u128);
+        let start = Self::ONE_STRONG | Self::pack_ptr(ptr);
         // println!("-- Init");
-        Self(AtomicU128::new(start), PhantomData::<T>)
+        Self {
+            state: AtomicU128::new(start),
+            weak: AtomicU64::new(1),
+            created: Instant::now(),
+            last_set: AtomicU64::new(0),
+            retired: AtomicPtr::new(std::ptr::null_mut()),
+            extras: AtomicPtr::new(std::ptr::null_mut()),
+            #[cfg(debug_assertions)]
+            canary: Self::CANARY,
+            phantom: PhantomData::<T>,
+        }
     }
 
     #[inline(always)]
     fn increment_strong(&self) {
-        self.0.fetch_add(Self::ONE_STRONG, Ordering::Release);
+        let old = self.state.fetch_add(Self::ONE_STRONG, Ordering::Release);
         // println!("-- Increment strong");
+
+        if Self::strong_of(old) >= Self::MAX_STRONG {
+            std::process::abort();
+        }
+    }
+
+    /// Returns the state before the increment
+    #[inline(always)]
+    fn increment_readers(&self) -> u128 {
+        let old = self.state.fetch_add(Self::ONE_READER, Ordering::Acquire);
+
+        if Self::readers_of(old) >= Self::MAX_READERS {
+            std::process::abort();
+        }
+
+        old
     }
 
+    /// Adds one strong or reader count (`one`) unless the value has already
+    /// been dropped, returning the state before the increment
     #[inline(always)]
-    fn decrement_strong(&self) -> u32 {
+    fn try_acquire(&self, one: u128) -> Option<u128> {
         loop {
-            let current = self.0.load(Ordering::Relaxed);
-            let mut strong = (current & Self::MASK_STRONG) >> 64;
-            strong -= 1;
-            let new = (current & !Self::MASK_STRONG) | (strong << 64);
+            let current = self.state.load(Ordering::Relaxed);
+
+            if current & (Self::MASK_STRONG | Self::MASK_READERS) == 0 {
+                return None;
+            }
+
+            if Self::strong_of(current) >= Self::MAX_STRONG || Self::readers_of(current) >= Self::MAX_READERS {
+                std::process::abort();
+            }
 
             if self
-                .0
-                .compare_exchange(current, new, Ordering::Release, Ordering::Relaxed)
+                .state
+                .compare_exchange(current, current + one, Ordering::Acquire, Ordering::Relaxed)
                 .is_ok()
             {
-                // println!("-- Decrement strong");
-                return strong as u32;
+                return Some(current);
             }
         }
     }
 
     #[inline(always)]
-    fn set_ptr(&self, ptr: *mut T) -> *mut T {
+    fn increment_weak(&self) {
+        self.weak.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Whether the calling handle is the only way to reach the value: one
+    /// strong handle, no readers and no weak handles that could upgrade
+    #[inline(always)]
+    fn is_unique(&self) -> bool {
+        let state = self.state.load(Ordering::Acquire);
+
+        self.weak.load(Ordering::Acquire) == 1
+            && Self::strong_of(state) == 1
+            && Self::readers_of(state) == 0
+    }
+
+    #[inline(always)]
+    fn decrement_weak(&self) -> u64 {
+        self.weak.fetch_sub(1, Ordering::AcqRel) - 1
+    }
+
+    /// Drops one strong or reader count (`one`). If that was the last of
+    /// both, the pointer is nulled in the same step and returned for dropping.
+    #[inline(always)]
+    fn release(&self, one: u128) -> Option<*mut T> {
         loop {
-            let current = self.0.load(Ordering::Relaxed);
-            let new = (current & !Self::MASK_PTR) | ptr as u128;
+            let current = self.state.load(Ordering::Relaxed);
+            let mut new = current - one;
+            let last = new & (Self::MASK_STRONG | Self::MASK_READERS) == 0;
 
-            if let Ok(value) =
-                self.0
-                    .compare_exchange(current, new, Ordering::Release, Ordering::Relaxed)
+            if last {
+                new &= !Self::MASK_PTR;
+            }
+
+            if self
+                .state
+                .compare_exchange(current, new, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
             {
-                // println!("-- Set ptr");
-                return (value & Self::MASK_PTR) as usize as *mut T;
+                return if last { Some(Self::ptr_of(current)) } else { None };
             }
         }
     }
-}
 
-impl<T> ArcCell<T> {
-    fn from_inner(ptr: NonNull<ArcCellInner<T>>) -> Self {
-        Self {
-            ptr,
-            phantom: PhantomData,
+    /// Drops the value and the weak reference shared by the strong and
+    /// reader handles, which frees the inner node once no WeakCell is left.
+    unsafe fn drop_value(this: NonNull<Self>, ptr: *mut T) {
+        // println!("-- Dropping {:x}", ptr as usize);
+        if !ptr.is_null() {
+            drop(Box::from_raw(ptr));
         }
+
+        // Nothing is left to read the retired values either
+        let retired = this.as_ref().retired.swap(std::ptr::null_mut(), Ordering::Acquire);
+        Self::free_retired(retired, drop);
+
+        // Nor to undo or share values, and weak handles may live on a while
+        if let Some(extras) = this.as_ref().extras_if_any() {
+            drop(std::mem::take(&mut *extras.undo.lock().unwrap_or_else(|e| e.into_inner())));
+            *extras.shared.lock().unwrap_or_else(|e| e.into_inner()) = (0, Weak::new());
+        }
+
+        drop(WeakCell { ptr: this, phantom: PhantomData });
     }
-}
 
-impl<T> Drop for ArcCell<T> {
-    fn drop(&mut self) {
-        if self.inner().decrement_strong() > 0 {
+    /// Queues `value`, which a write has just swapped out, to be freed by a
+    /// later `reclaim` instead of now, as readers may still be using it.
+    /// `free` is for values that are not a plain box.
+    fn retire(&self, value: *mut T, free: Option<unsafe fn(*mut T)>) {
+        let entry = Box::into_raw(Box::new(Retired {
+            value,
+            free,
+            next: std::ptr::null_mut(),
+        }));
+
+        self.push_retired(entry, entry);
+    }
+
+    /// Pushes the chain `first..=last` onto the retired list
+    fn push_retired(&self, first: *mut Retired<T>, last: *mut Retired<T>) {
+        let mut head = self.retired.load(Ordering::Relaxed);
+
+        loop {
+            unsafe { (*last).next = head };
+
+            match self
+                .retired
+                .compare_exchange_weak(head, first, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// Frees the retired values if no readers are live, passing plain boxes
+    /// to `dispose`. A reader that could have seen a retired value was
+    /// counted before the write that retired it, so once the count is zero
+    /// after the list was taken, none of them can still be reading.
+    ///
+    /// While readers are live the list is left alone, so this is O(1) and
+    /// the list grows by one entry per retiring write. It is only bounded by
+    /// the reader count reaching zero: with a reader held for good, every
+    /// retired value is kept until a retiring write after it is released, or
+    /// until the cell is dropped.
+    fn reclaim<F: FnMut(Box<T>)>(&self, dispose: F) {
+        if self.retired.load(Ordering::Relaxed).is_null()
+            || Self::readers_of(self.state.load(Ordering::Acquire)) != 0
+        {
             return;
         }
 
-        // Synchronise and drop
-        let (_, ptr) = self.inner().set_ptr_null();
-        // println!("-- Dropping {:x}", ptr as usize);
+        let head = self.retired.swap(std::ptr::null_mut(), Ordering::Acquire);
 
-        drop(unsafe { Box::from_raw(ptr) });
+        if head.is_null() {
+            return;
+        }
 
-        // We can deallocate the inner pointer now
-        // println!("-- Dropping inner");
-        unsafe {
-            Box::from_raw(self.ptr.as_ptr());
+        if Self::readers_of(self.state.load(Ordering::Acquire)) == 0 {
+            unsafe { Self::free_retired(head, dispose) };
+            return;
+        }
+
+        // A reader arrived between the two checks. Putting the list back
+        // only needs its end if something else was retired in the meantime.
+        if self
+            .retired
+            .compare_exchange(std::ptr::null_mut(), head, Ordering::Release, Ordering::Relaxed)
+            .is_err()
+        {
+            let mut last = head;
+            while let Some(next) = unsafe { (*last).next.as_mut() } {
+                last = next;
+            }
+            self.push_retired(head, last);
         }
     }
-}
 
-impl<T> ArcCell<T> {
-    #[inline]
-    pub fn new(data: Box<T>) -> ArcCell<T> {
-        let x = Box::new(ArcCellInner::new(Box::into_raw(data)));
+    unsafe fn free_retired<F: FnMut(Box<T>)>(mut entry: *mut Retired<T>, mut dispose: F) {
+        while !entry.is_null() {
+            let Retired { value, free, next } = *Box::from_raw(entry);
 
-        Self::from_inner(unsafe { NonNull::new_unchecked(Box::into_raw(x) as *mut _) })
+            match free {
+                Some(free) => free(value),
+                None => dispose(Box::from_raw(value)),
+            }
+
+            entry = next;
+        }
     }
 
-    /// Returns old data
-    #[inline]
-    pub fn set(&self, data: Box<T>) -> Box<T> {
-        let old_ptr = self.inner().set_ptr(Box::into_raw(data));
-        unsafe { Box::from_raw(old_ptr) }
+    #[inline(always)]
+    fn touch(&self) {
+        let nanos = self.created.elapsed().as_nanos() as u64;
+        self.last_set.fetch_max(nanos, Ordering::Release);
+
+        // Writes only pay for the notify, which takes a lock, while something
+        // is watching. The fence pairs with the one in `get_and_watch`: either
+        // this sees the watcher or the watcher sees this write's version.
+        #[cfg(feature = "async")]
+        {
+            std::sync::atomic::fence(Ordering::SeqCst);
+
+            if let Some(extras) = self.extras_if_any() {
+                if extras.watchers.load(Ordering::Relaxed) != 0 {
+                    extras.changed.notify_waiters();
+                }
+            }
+        }
     }
 
+    /// The side table, allocating it if this is its first use
     #[inline]
-    pub fn get(&self) -> ArcRef<T> {
-        self.inner().increment_strong();
-        ArcRef { ptr: self.ptr, phantom: self.phantom }
+    fn extras(&self) -> &Extras<T> {
+        if let Some(extras) = self.extras_if_any() {
+            return extras;
+        }
+
+        let new = Box::into_raw(Box::new(Extras {
+            hash: AtomicU128::new(0),
+            shared: Mutex::new((0, Weak::new())),
+            fingerprints: Mutex::new(VecDeque::new()),
+            undo: Mutex::new(VecDeque::new()),
+            generation: AtomicU64::new(0),
+            #[cfg(feature = "async")]
+            changed: tokio::sync::Notify::new(),
+            #[cfg(feature = "async")]
+            watchers: AtomicU64::new(0),
+        }));
+
+        match self
+            .extras
+            .compare_exchange(std::ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => unsafe { &*new },
+            Err(actual) => {
+                drop(unsafe { Box::from_raw(new) });
+                unsafe { &*actual }
+            }
+        }
     }
 
-    #[inline]
-    pub fn strong_count(&self) -> u64 {
-        self.inner().strong_count()
+    /// The side table, unless nothing has needed it yet
+    #[inline(always)]
+    fn extras_if_any(&self) -> Option<&Extras<T>> {
+        unsafe { self.extras.load(Ordering::Acquire).as_ref() }
     }
 
-    #[inline]
-    fn inner(&self) -> &ArcCellInner<T> {
-        unsafe { self.ptr.as_ref() }
+    const HASH_VALID: u128 = 1 << 96;
+
+    #[inline(always)]
+    fn store_hash(&self, version: u64, hash: u64) {
+        let value = Self::HASH_VALID | ((version as u128) << 64) | hash as u128;
+        self.extras().hash.store(value, Ordering::Release);
     }
-}
 
-impl<T> ArcRef<T> {
-    #[inline]
-    fn inner(&self) -> &ArcCellInner<T> {
-        unsafe { self.ptr.as_ref() }
+    /// The cached hash, if it was computed for the current version
+    #[inline(always)]
+    fn cached_hash(&self) -> Option<u64> {
+        self.cached_hash_at(self.version())
     }
-}
 
-impl<T: Display> Display for ArcRef<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Display::fmt(unsafe { &*self.inner().ptr() }, f)
+    /// Clears the dirty bit, returning whether it was set
+    #[inline(always)]
+    fn take_dirty(&self) -> bool {
+        self.state.fetch_and(!Self::MASK_DIRTY, Ordering::AcqRel) & Self::MASK_DIRTY != 0
     }
-}
 
-impl<T: Debug> Debug for ArcRef<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Debug::fmt(unsafe { &*self.inner().ptr() }, f)
+    /// Zeroes the version, returning the one it replaced
+    #[inline(always)]
+    fn reset_version(&self) -> u64 {
+        let old = self.state.fetch_and(!Self::MASK_VERSION, Ordering::AcqRel);
+
+        // The cached hash and shared snapshot are tagged with versions from
+        // the old numbering
+        if let Some(extras) = self.extras_if_any() {
+            extras.hash.store(0, Ordering::Release);
+            *extras.shared.lock().unwrap_or_else(|e| e.into_inner()) = (0, Weak::new());
+        }
+
+        Self::version_of(old)
     }
-}
 
-impl<T> Deref for ArcRef<T> {
-    type Target = T;
+    #[inline(always)]
+    fn cached_hash_at(&self, version: u64) -> Option<u64> {
+        let value = self.extras_if_any()?.hash.load(Ordering::Acquire);
 
-    fn deref(&self) -> &Self::Target {
-        unsafe { &*self.inner().ptr() }
+        if value & Self::HASH_VALID == 0 || ((value >> 64) & 0xFF_FFFF) as u64 != version {
+            return None;
+        }
+
+        Some(value as u64)
     }
-}
 
-impl<T> Clone for ArcCell<T> {
-    fn clone(&self) -> Self {
-        self.inner().increment_strong();
+    #[inline(always)]
+    fn last_set(&self) -> Instant {
+        self.created + Duration::from_nanos(self.last_set.load(Ordering::Acquire))
+    }
 
-        Self {
-            ptr: self.ptr,
-            phantom: PhantomData,
+    /// Moves the last write time to now if at least `min_interval` has passed
+    /// since it, so only one of several racing writers gets the window
+    #[inline(always)]
+    fn claim_set_window(&self, min_interval: Duration) -> bool {
+        let now = self.created.elapsed().as_nanos() as u64;
+        let min_interval = min_interval.as_nanos() as u64;
+
+        self.last_set
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |last| {
+                (now.saturating_sub(last) >= min_interval).then_some(now)
+            })
+            .is_ok()
+    }
+
+    #[inline(always)]
+    fn age(&self) -> Duration {
+        let last_set = Duration::from_nanos(self.last_set.load(Ordering::Acquire));
+        self.created.elapsed().saturating_sub(last_set)
+    }
+
+    /// Returns the state before the swap
+    #[inline(always)]
+    fn set_ptr(&self, ptr: *mut T) -> u128 {
+        self.set_ptr_ordered(ptr, Ordering::Release)
+    }
+
+    #[inline(always)]
+    fn set_ptr_ordered(&self, ptr: *mut T, order: Ordering) -> u128 {
+        loop {
+            let current = self.state.load(Ordering::Relaxed);
+            let new = Self::with_ptr(current, ptr);
+
+            if let Ok(value) =
+                self.state
+                    .compare_exchange(current, new, order, Ordering::Relaxed)
+            {
+                // println!("-- Set ptr");
+                self.touch();
+                return value;
+            }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Swaps in `new` only if no write has happened since `version`, returning
+    /// the old pointer, or the current version on failure.
+    #[inline(always)]
+    fn compare_exchange_version(&self, version: u64, new: *mut T) -> Result<*mut T, u64> {
+        loop {
+            let value = self.state.load(Ordering::Acquire);
 
-    #[test]
-    fn big_test() {
-        let v = ArcCell::new(Box::new("Something horrible".to_string()));
-        let v0 = ArcCell::clone(&v);
-        let t = std::thread::spawn(move || {
-            println!("{}", v0.get());
-            let v1 = ArcCell::clone(&v0);
-            let _t = std::thread::spawn(move || {
-                println!("{}", v1.get());
-                v1.set(Box::new("Some other value again heh".to_string()));
-            });
-            v0.set(Box::new("Some other value".to_string()));
-        });
-        let v0 = ArcCell::clone(&v);
-        let _t = std::thread::spawn(move || {
-            println!("{}", v0.get());
-            let v1 = ArcCell::clone(&v0);
-            let _t = std::thread::spawn(move || {
+            if Self::version_of(value) != version {
+                return Err(Self::version_of(value));
+            }
+
+            if self
+                .state
+                .compare_exchange(value, Self::with_ptr(value, new), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.touch();
+                return Ok(Self::ptr_of(value));
+            }
+        }
+    }
+
+    /// Swaps in `new` only while at most `max_readers` readers are live, as
+    /// part of the same CAS. Returns the old pointer, or the reader count.
+    #[inline(always)]
+    fn set_ptr_if_readers_at_most(&self, new: *mut T, max_readers: u64) -> Result<*mut T, u64> {
+        loop {
+            let value = self.state.load(Ordering::Acquire);
+
+            if Self::readers_of(value) > max_readers {
+                return Err(Self::readers_of(value));
+            }
+
+            if self
+                .state
+                .compare_exchange(value, Self::with_ptr(value, new), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.touch();
+                return Ok(Self::ptr_of(value));
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn compare_exchange_ptr(&self, current: *mut T, new: *mut T) -> Result<(), *mut T> {
+        loop {
+            let value = self.state.load(Ordering::Acquire);
+            let ptr = Self::ptr_of(value);
+
+            if ptr != current {
+                return Err(ptr);
+            }
+
+            let new = Self::with_ptr(value, new);
+
+            if self
+                .state
+                .compare_exchange(value, new, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                self.touch();
+                return Ok(());
+            }
+        }
+    }
+}
+
+impl<T> Drop for ArcCellInner<T> {
+    fn drop(&mut self) {
+        let extras = *self.extras.get_mut();
+
+        if !extras.is_null() {
+            drop(unsafe { Box::from_raw(extras) });
+        }
+    }
+}
+
+impl<T> ArcCell<T> {
+    fn from_inner(ptr: NonNull<ArcCellInner<T>>) -> Self {
+        Self {
+            ptr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for ArcCell<T> {
+    fn drop(&mut self) {
+        if let Some(ptr) = self.inner().release(ArcCellInner::<T>::ONE_STRONG) {
+            unsafe { ArcCellInner::drop_value(self.ptr, ptr) }
+        }
+    }
+}
+
+impl<T> Drop for ArcRef<T> {
+    fn drop(&mut self) {
+        if let Some(ptr) = self.inner().release(ArcCellInner::<T>::ONE_READER) {
+            unsafe { ArcCellInner::drop_value(self.ptr, ptr) }
+        }
+    }
+}
+
+impl<T> Drop for WeakCell<T> {
+    fn drop(&mut self) {
+        if self.inner().decrement_weak() > 0 {
+            return;
+        }
+
+        // We can deallocate the inner pointer now
+        // println!("-- Dropping inner");
+        unsafe {
+            #[cfg(debug_assertions)]
+            std::ptr::write_volatile(
+                std::ptr::addr_of_mut!((*self.ptr.as_ptr()).canary),
+                ArcCellInner::<T>::CANARY_FREED,
+            );
+            drop(Box::from_raw(self.ptr.as_ptr()));
+        }
+    }
+}
+
+impl<T> ArcCell<T> {
+    #[inline]
+    pub fn new(data: Box<T>) -> ArcCell<T> {
+        let x = Box::new(ArcCellInner::new(Box::into_raw(data)));
+
+        Self::from_inner(unsafe { NonNull::new_unchecked(Box::into_raw(x) as *mut _) })
+    }
+
+    /// Creates a cell with no value, to be filled by [`init_once`](Self::init_once).
+    ///
+    /// # Safety
+    ///
+    /// Until `init_once` has succeeded, the cell must not be read or written
+    /// through any other method; only `init_once`, `is_empty`, cloning the
+    /// handle (so several threads can race to initialise it) and dropping
+    /// the cell are allowed.
+    #[inline]
+    pub unsafe fn empty() -> ArcCell<T> {
+        let x = Box::new(ArcCellInner::new(std::ptr::null()));
+
+        Self::from_inner(NonNull::new_unchecked(Box::into_raw(x) as *mut _))
+    }
+
+    /// Whether the cell was created with `empty` and has not been initialised
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner().ptr().is_null()
+    }
+
+    /// Installs `data` if the cell is still empty, returning a reference to it.
+    /// If another caller got there first, `data` is dropped and the existing
+    /// value is returned as `Err`.
+    pub fn init_once(&self, data: Box<T>) -> Result<ArcRef<T>, ArcRef<T>> {
+        let new = Box::into_raw(data);
+
+        match self.inner().compare_exchange_ptr(std::ptr::null_mut(), new) {
+            Ok(()) => Ok(self.get()),
+            Err(_) => {
+                drop(unsafe { Box::from_raw(new) });
+                Err(self.get())
+            }
+        }
+    }
+
+    /// Returns old data
+    #[inline]
+    pub fn set(&self, data: Box<T>) -> Box<T> {
+        let old = self.inner().set_ptr(Box::into_raw(data));
+        unsafe { Box::from_raw(ArcCellInner::ptr_of(old)) }
+    }
+
+    /// Installs `data`, keeping the old value in the returned token so the
+    /// write can be rolled back until the token is committed or dropped.
+    pub fn set_with_rollback(&self, data: Box<T>) -> RollbackToken<'_, T> {
+        let new = Box::into_raw(data);
+        let old = self.inner().set_ptr(new);
+
+        RollbackToken {
+            cell: self,
+            version: ArcCellInner::<T>::version_of(ArcCellInner::with_ptr(old, new)),
+            old: Some(unsafe { Box::from_raw(ArcCellInner::ptr_of(old)) }),
+        }
+    }
+
+    /// Like `set`, but publishes with `order`, which must be `Release`,
+    /// `AcqRel` or `SeqCst`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `order` is `Relaxed` or `Acquire`, neither of which publishes
+    /// the new value.
+    #[inline]
+    pub fn set_ordered(&self, data: Box<T>, order: Ordering) -> Box<T> {
+        match order {
+            Ordering::Release | Ordering::AcqRel | Ordering::SeqCst => {}
+            _ => panic!("{:?} cannot be used to publish a value", order),
+        }
+
+        let old = self.inner().set_ptr_ordered(Box::into_raw(data), order);
+        unsafe { Box::from_raw(ArcCellInner::ptr_of(old)) }
+    }
+
+    /// Reserves a write whose value is supplied later via [`Reservation::fill`],
+    /// which only goes ahead if nothing else has been written in between.
+    /// Dropping the reservation unfilled leaves the cell untouched.
+    #[inline]
+    pub fn reserve(&self) -> Reservation<'_, T> {
+        Reservation {
+            cell: self,
+            version: self.version(),
+        }
+    }
+
+    /// Starts an optimistic write that [`WriteTicket::commit`] only completes
+    /// if no other write has happened in the meantime.
+    #[inline]
+    pub fn begin_write(&self) -> WriteTicket<'_, T> {
+        WriteTicket {
+            cell: self,
+            version: self.version(),
+        }
+    }
+
+    /// Installs `data` and sends the old value down `tx`, leaving the receiver
+    /// to process or drop it off the writer's thread. If the receiver has hung
+    /// up, the old value is returned in the error.
+    #[inline]
+    pub fn set_sending(&self, data: Box<T>, tx: &mpsc::Sender<Box<T>>) -> Result<(), mpsc::SendError<Box<T>>> {
+        tx.send(self.set(data))
+    }
+
+    /// Installs `data` and caches its hash, returning it. The old value is
+    /// dropped once no reader can still be using it.
+    pub fn set_hashed(&self, data: Box<T>) -> u64
+    where
+        T: Hash,
+    {
+        let hash = hash_of(&*data);
+        let new = Box::into_raw(data);
+        let old = self.inner().set_ptr(new);
+
+        let version = ArcCellInner::<T>::version_of(ArcCellInner::with_ptr(old, new));
+        self.inner().store_hash(version, hash);
+
+        self.retire(ArcCellInner::ptr_of(old));
+        hash
+    }
+
+    /// The hash cached by the last `set_hashed`, or `None` if the cell has
+    /// been written some other way since.
+    #[inline]
+    pub fn cached_hash(&self) -> Option<u64> {
+        self.inner().cached_hash()
+    }
+
+    /// Installs `new` only if the current value hashes to `expected_hash`,
+    /// returning old data, or `new` back on a mismatch. The hash cached by
+    /// `set_hashed` is used when it is current, otherwise the value is hashed.
+    ///
+    /// This trades a full comparison for a hash comparison, so two different
+    /// values whose hashes collide are treated as equal. With a 64-bit hash
+    /// that is unlikely, but it is not impossible.
+    pub fn compare_and_swap_hash(&self, expected_hash: u64, new: Box<T>) -> Result<Box<T>, Box<T>>
+    where
+        T: Hash,
+    {
+        let new = Box::into_raw(new);
+
+        loop {
+            let (pin, state) = self.pinned();
+            let version = ArcCellInner::<T>::version_of(state);
+            let hash = match self.inner().cached_hash_at(version) {
+                Some(hash) => hash,
+                None => hash_of(unsafe { &*ArcCellInner::<T>::ptr_of(state) }),
+            };
+            drop(pin);
+
+            if hash != expected_hash {
+                return Err(unsafe { Box::from_raw(new) });
+            }
+
+            if let Ok(old) = self.inner().compare_exchange_version(version, new) {
+                return Ok(unsafe { Box::from_raw(old) });
+            }
+        }
+    }
+
+    /// Installs `data` and wakes every task waiting on `notify`, so they can
+    /// re-read the cell. Returns old data.
+    #[cfg(feature = "async")]
+    #[inline]
+    pub fn set_notify(&self, data: Box<T>, notify: &tokio::sync::Notify) -> Box<T> {
+        let old = self.set(data);
+        notify.notify_waiters();
+        old
+    }
+
+    /// Installs `data` and moves the old value into `out`, freeing its box
+    /// rather than handing it back.
+    ///
+    /// # Safety
+    ///
+    /// `out` is written without dropping whatever it held, so an initialised
+    /// value in it is leaked, and the caller owns the value written and must
+    /// treat `out` as initialised afterwards. As with `set`, nothing may still
+    /// be reading the old value through a reference taken from the cell.
+    #[inline]
+    pub unsafe fn swap_into(&self, data: Box<T>, out: &mut MaybeUninit<T>) {
+        out.write(*self.set(data));
+    }
+
+    /// Like `set`, but also returns the time spent in the swap's retry loop,
+    /// for measuring write latency under contention
+    #[inline]
+    pub fn set_timed(&self, data: Box<T>) -> (Box<T>, Duration) {
+        let data = Box::into_raw(data);
+
+        let start = Instant::now();
+        let old = self.inner().set_ptr(data);
+        let elapsed = start.elapsed();
+
+        (unsafe { Box::from_raw(ArcCellInner::ptr_of(old)) }, elapsed)
+    }
+
+    /// Installs `data` and emits a `tracing` event at debug level with the
+    /// `Debug` renderings of the old and new values. Returns old data, which
+    /// is held until the event has been emitted.
+    #[cfg(feature = "tracing")]
+    pub fn set_traced(&self, data: Box<T>) -> Box<T>
+    where
+        T: Debug,
+    {
+        let old = self.set(data);
+        let new = self.get();
+        tracing::debug!(old = ?old, new = ?new, "ArcCell set");
+        drop(new);
+        old
+    }
+
+    /// Serializes the current value to a `serde_json::Value`, holding a
+    /// reference to it for the duration
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> serde_json::Result<serde_json::Value>
+    where
+        T: serde::Serialize,
+    {
+        let value = self.get();
+        serde_json::to_value(&*value)
+    }
+
+    /// Builds a new collection from `iter` and installs it, returning the
+    /// old collection by value
+    #[inline]
+    pub fn replace_from_iter<A, I: IntoIterator<Item = A>>(&self, iter: I) -> T
+    where
+        T: FromIterator<A>,
+    {
+        *self.set(Box::new(iter.into_iter().collect()))
+    }
+
+    /// Installs `data` only if `validate` accepts it and returns the old value.
+    /// A rejected value is handed back with the error and the cell is untouched.
+    #[inline]
+    pub fn set_validated<F: FnOnce(&T) -> Result<(), E>, E>(
+        &self,
+        data: Box<T>,
+        validate: F,
+    ) -> Result<Box<T>, (Box<T>, E)> {
+        match validate(&data) {
+            Ok(()) => Ok(self.set(data)),
+            Err(e) => Err((data, e)),
+        }
+    }
+
+    /// Installs `data` and returns the old value, unless the last write (or
+    /// the cell's creation) was less than `min_interval` ago, in which case
+    /// `data` is handed back.
+    #[inline]
+    pub fn set_debounced(&self, data: Box<T>, min_interval: Duration) -> Result<Box<T>, Box<T>> {
+        if self.inner().claim_set_window(min_interval) {
+            Ok(self.set(data))
+        } else {
+            Err(data)
+        }
+    }
+
+    /// Installs the value built by `f` and returns the old one. `f` is only
+    /// called once the swap is going ahead, which for now is always.
+    #[inline]
+    pub fn swap_lazy_default<F: FnOnce() -> Box<T>>(&self, f: F) -> Box<T> {
+        self.set(f())
+    }
+
+    /// Installs `data` and returns the old value, unless more than
+    /// `max_readers` readers are live, in which case `data` is handed back.
+    #[inline]
+    pub fn set_backpressured(&self, data: Box<T>, max_readers: u64) -> Result<Box<T>, Box<T>> {
+        let new = Box::into_raw(data);
+
+        match self.inner().set_ptr_if_readers_at_most(new, max_readers) {
+            Ok(old) => Ok(unsafe { Box::from_raw(old) }),
+            Err(_) => Err(unsafe { Box::from_raw(new) }),
+        }
+    }
+
+    /// Installs `data`, stamps the cell with `gen`'s current number and
+    /// returns the old value. The stamp is stored just after the value, so a
+    /// reader can briefly see the new value with the previous stamp.
+    #[inline]
+    pub fn update_tracked(&self, gen: &Generation, data: Box<T>) -> Box<T> {
+        let old = self.set(data);
+        self.inner().extras().generation.store(gen.current(), Ordering::Release);
+        old
+    }
+
+    /// The generation stamped by the last [`update_tracked`](Self::update_tracked),
+    /// or `0` if there has been none
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.inner()
+            .extras_if_any()
+            .map_or(0, |extras| extras.generation.load(Ordering::Acquire))
+    }
+
+    /// Installs `data` and returns the old value, along with whether the old
+    /// value satisfied `is_default`, i.e. whether this was the first real write.
+    #[inline]
+    pub fn set_first<F: Fn(&T) -> bool>(&self, data: Box<T>, is_default: F) -> (Box<T>, bool) {
+        let old = self.set(data);
+        let first = is_default(&old);
+        (old, first)
+    }
+
+    /// Installs `data`, keeping the old value so [`undo`](Self::undo) can
+    /// restore it. At most `max_undo` levels are kept, oldest dropped first.
+    ///
+    /// Writes made any other way in between invalidate the history: a stale
+    /// history is cleared by the next `set_undoable`, or by an `undo` that
+    /// finds the cell has moved on.
+    pub fn set_undoable(&self, data: Box<T>, max_undo: usize) {
+        let mut undo = self.inner().extras().undo.lock().unwrap_or_else(|e| e.into_inner());
+        let new = Box::into_raw(data);
+        let old = self.inner().set_ptr(new);
+        let old_version = ArcCellInner::<T>::version_of(old);
+
+        // Every entry was in the cell once, so readers may still have it
+        let keep = if undo.back().is_some_and(|(version, _)| *version != old_version) {
+            0
+        } else {
+            max_undo.saturating_sub(1)
+        };
+        let excess = undo.len().saturating_sub(keep);
+        for (_, value) in undo.drain(..excess) {
+            self.retire(Box::into_raw(value));
+        }
+
+        if max_undo == 0 {
+            return self.retire(ArcCellInner::ptr_of(old));
+        }
+
+        undo.push_back((
+            ArcCellInner::<T>::next_version(old_version),
+            unsafe { Box::from_raw(ArcCellInner::ptr_of(old)) },
+        ));
+    }
+
+    /// Restores the value displaced by the last [`set_undoable`](Self::set_undoable)
+    /// that has not been undone yet. Returns `false` if there is nothing to
+    /// undo or another write has happened since, which also clears the history.
+    pub fn undo(&self) -> bool {
+        let mut undo = self.inner().extras().undo.lock().unwrap_or_else(|e| e.into_inner());
+
+        let (version, old) = match undo.pop_back() {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        let old = Box::into_raw(old);
+
+        match self.inner().compare_exchange_version(version, old) {
+            Ok(undone) => {
+                self.retire(undone);
+
+                // The restore is itself a write, which the next level must follow
+                if let Some((next, _)) = undo.back_mut() {
+                    *next = ArcCellInner::<T>::next_version(version);
+                }
+
+                true
+            }
+            Err(_) => {
+                self.retire(old);
+                for (_, value) in undo.drain(..) {
+                    self.retire(Box::into_raw(value));
+                }
+                false
+            }
+        }
+    }
+
+    /// Installs `data`, dropping the old value once no reader can still be
+    /// using it, and returns whether its fingerprint `fp` is new among the
+    /// last 16 values written this way. Repeats and oscillations between a
+    /// few values report `false`.
+    pub fn set_fingerprinted<F: Fn(&T) -> u64>(&self, data: Box<T>, fp: F) -> bool {
+        let fingerprint = fp(&data);
+        let mut recent = self
+            .inner()
+            .extras()
+            .fingerprints
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let old = self.inner().set_ptr(Box::into_raw(data));
+        self.retire(ArcCellInner::ptr_of(old));
+
+        if let Some(i) = recent.iter().position(|&seen| seen == fingerprint) {
+            recent.remove(i);
+            recent.push_back(fingerprint);
+            return false;
+        }
+
+        if recent.len() == ArcCellInner::<T>::RECENT_FINGERPRINTS {
+            recent.pop_front();
+        }
+        recent.push_back(fingerprint);
+        true
+    }
+
+    /// Installs values built by `make` until `post` holds for what the cell
+    /// contains after the write, so a racing writer that clobbers the value
+    /// just causes another attempt. Old values are dropped once no reader
+    /// can still be using them.
+    pub fn set_until<F: Fn(&T) -> bool>(&self, mut make: impl FnMut() -> Box<T>, post: F) {
+        loop {
+            let old = self.inner().set_ptr(Box::into_raw(make()));
+            self.retire(ArcCellInner::ptr_of(old));
+
+            if post(&self.get()) {
+                return;
+            }
+        }
+    }
+
+    /// Installs `candidate` if `cmp` orders it after the current value,
+    /// retrying if another writer gets in first, and drops the value it loses
+    /// to. Returns whether `candidate` was installed.
+    ///
+    /// The install is conditional on the version that was compared against,
+    /// so a smaller value cannot slip in between the comparison and the swap.
+    pub fn set_max<F: Fn(&T, &T) -> std::cmp::Ordering>(&self, candidate: Box<T>, cmp: F) -> bool {
+        let candidate = Box::into_raw(candidate);
+
+        loop {
+            let (pin, state) = self.pinned();
+            let current = unsafe { &*ArcCellInner::ptr_of(state) };
+
+            if cmp(unsafe { &*candidate }, current) != std::cmp::Ordering::Greater {
+                drop(pin);
+                drop(unsafe { Box::from_raw(candidate) });
+                return false;
+            }
+
+            let swapped = self
+                .inner()
+                .compare_exchange_version(ArcCellInner::<T>::version_of(state), candidate);
+            drop(pin);
+
+            if let Ok(old) = swapped {
+                self.retire(old);
+                return true;
+            }
+        }
+    }
+
+    /// Replaces the value with `f(&current)`, retrying if another writer
+    /// gets in first. Each attempt reads under a reader and commits against
+    /// the version it read, and the displaced value is retired, since a
+    /// racing update may still be reading it.
+    fn update<F: FnMut(&T) -> Box<T>>(&self, mut f: F) {
+        loop {
+            let (pin, state) = self.pinned();
+            let new = Box::into_raw(f(unsafe { &*ArcCellInner::ptr_of(state) }));
+            let swapped = self
+                .inner()
+                .compare_exchange_version(ArcCellInner::<T>::version_of(state), new);
+            drop(pin);
+
+            match swapped {
+                Ok(old) => return self.retire(old),
+                Err(_) => drop(unsafe { Box::from_raw(new) }),
+            }
+        }
+    }
+
+    /// Installs `data` if the cell is still at `version`, returning old data,
+    /// or `data` back if not
+    #[inline]
+    fn set_at_version(&self, version: u64, data: Box<T>) -> Result<Box<T>, Box<T>> {
+        let new = Box::into_raw(data);
+
+        match self.inner().compare_exchange_version(version, new) {
+            Ok(old) => Ok(unsafe { Box::from_raw(old) }),
+            Err(_) => Err(unsafe { Box::from_raw(new) }),
+        }
+    }
+
+    /// Takes a reader along with the state it was counted in. The value at
+    /// the state's pointer cannot be reclaimed while the reader is held.
+    #[inline]
+    fn pinned(&self) -> (ArcRef<T>, u128) {
+        let state = self.inner().increment_readers();
+
+        (ArcRef::counted(self.ptr, state), state)
+    }
+
+    /// Drops `old`, just swapped out by one of the cell's own writes, once no
+    /// reader can still be using it
+    #[inline]
+    fn retire(&self, old: *mut T) {
+        self.inner().retire(old, None);
+        self.inner().reclaim(drop);
+    }
+
+    /// Installs `new` if the cell has not been written since `expected` was
+    /// taken from it. Otherwise `on_fail` is called with the current value to
+    /// produce the next attempt, until one succeeds. Returns old data.
+    ///
+    /// The check is by version rather than address, so a value that was
+    /// swapped out and back in, or a new one reusing its allocation, still
+    /// counts as a change.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `expected` was taken from a different cell.
+    pub fn compare_and_swap_or_else<F: FnMut(&T) -> Box<T>>(
+        &self,
+        expected: &ArcRef<T>,
+        new: Box<T>,
+        mut on_fail: F,
+    ) -> Box<T> {
+        assert!(expected.ptr == self.ptr, "expected reference is from a different cell");
+
+        let mut version = expected.version;
+        let mut new = Box::into_raw(new);
+
+        loop {
+            match self.inner().compare_exchange_version(version, new) {
+                Ok(old) => return unsafe { Box::from_raw(old) },
+                Err(_) => {
+                    drop(unsafe { Box::from_raw(new) });
+
+                    let (pin, state) = self.pinned();
+                    new = Box::into_raw(on_fail(unsafe { &*ArcCellInner::ptr_of(state) }));
+                    version = ArcCellInner::<T>::version_of(state);
+                    drop(pin);
+                }
+            }
+        }
+    }
+
+    /// Applies `updates` in order, folding each one over the current value.
+    /// Every step retries on its own if another writer intervenes, so the
+    /// batch as a whole is not atomic.
+    pub fn fold<U, I, F>(&self, updates: I, f: F)
+    where
+        U: Clone,
+        I: IntoIterator<Item = U>,
+        F: Fn(&T, U) -> Box<T>,
+    {
+        for update in updates {
+            self.update(|current| f(current, update.clone()));
+        }
+    }
+
+    /// Applies `f` to every cell in `cells`, each with its own retry loop.
+    ///
+    /// This is not atomic across the group: readers can observe some cells
+    /// updated and others not, and each cell may see writes interleaved
+    /// between other cells' updates.
+    pub fn update_all<F: Fn(&T) -> Box<T>>(cells: &[ArcCell<T>], f: F) {
+        for cell in cells {
+            cell.update(&f);
+        }
+    }
+
+    /// Installs `data`, returning the old value along with the number of
+    /// live `ArcRef`s at the moment of the swap
+    #[inline]
+    pub fn set_report_readers(&self, data: Box<T>) -> (Box<T>, u64) {
+        let old = self.inner().set_ptr(Box::into_raw(data));
+        let readers = ArcCellInner::<T>::readers_of(old);
+
+        (unsafe { Box::from_raw(ArcCellInner::ptr_of(old)) }, readers)
+    }
+
+    #[inline]
+    pub fn get(&self) -> ArcRef<T> {
+        self.pinned().0
+    }
+
+    /// Takes a reference to the value together with the counters and version
+    /// from the same atomic read. The last write time lives outside the state
+    /// word and is read just after, so it may already reflect a newer write.
+    pub fn full_snapshot(&self) -> FullSnapshot<T> {
+        let (value, state) = self.pinned();
+
+        FullSnapshot {
+            value,
+            strong: ArcCellInner::<T>::strong_of(state),
+            readers: ArcCellInner::<T>::readers_of(state) + 1,
+            version: ArcCellInner::<T>::version_of(state),
+            last_set: self.inner().last_set(),
+        }
+    }
+
+    /// Takes a reference to the value, asserting `invariant` holds for it in
+    /// debug builds. Release builds skip the check.
+    #[inline]
+    pub fn get_checked<F: Fn(&T) -> bool>(&self, invariant: F) -> ArcRef<T> {
+        let value = self.get();
+        debug_assert!(invariant(&value), "ArcCell value violates the read-site invariant");
+        value
+    }
+
+    /// Takes a reference to the value with its type erased, so reads of cells
+    /// of different types can be stored together. `downcast_ref` recovers `T`.
+    ///
+    /// The state word has no room for a fat pointer, so this is an
+    /// `ErasedRef` around the reader rather than an `ArcRef<dyn Any>`.
+    #[inline]
+    pub fn erase(&self) -> ErasedRef
+    where
+        T: Any + Send + Sync,
+    {
+        ErasedRef { inner: Box::new(self.get()) }
+    }
+
+    /// Returns the current value as an `Arc`. While an `Arc` handed out for
+    /// the current version is still alive, further calls share it; otherwise
+    /// the value is cloned into a fresh one.
+    pub fn shared_snapshot(&self) -> Arc<T>
+    where
+        T: Clone,
+    {
+        let (value, state) = self.pinned();
+        let version = ArcCellInner::<T>::version_of(state);
+        let mut shared = self.inner().extras().shared.lock().unwrap_or_else(|e| e.into_inner());
+
+        if shared.0 == version {
+            if let Some(arc) = shared.1.upgrade() {
+                return arc;
+            }
+        }
+
+        // Cloned from the pointer read with `version`, not through `value`,
+        // which would read whatever the cell holds by now
+        let arc = Arc::new(T::clone(unsafe { &*ArcCellInner::<T>::ptr_of(state) }));
+        drop(value);
+
+        *shared = (version, Arc::downgrade(&arc));
+        arc
+    }
+
+    /// Takes a reference to the value and pairs it with `ctx`, for threading
+    /// context through a pipeline
+    #[inline]
+    pub fn get_with<C>(&self, ctx: C) -> (ArcRef<T>, C) {
+        (self.get(), ctx)
+    }
+
+    /// Returns a handle whose [`get`](Derived::get) is `f(&value)`, computed
+    /// at most once per version of the cell. Each handle has its own cache.
+    #[inline]
+    pub fn derived<U, F>(&self, f: F) -> Derived<T, U>
+    where
+        F: Fn(&T) -> U + Send + Sync + 'static,
+    {
+        Derived {
+            cell: self.clone(),
+            f: Box::new(f),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Takes a reference to the value along with the number of live readers,
+    /// counting the returned one, from the same atomic operation. Servers can
+    /// use the count to shed load when too many reads are in flight.
+    #[inline]
+    pub fn get_load(&self) -> (ArcRef<T>, u64) {
+        let (value, state) = self.pinned();
+
+        (value, ArcCellInner::<T>::readers_of(state) + 1)
+    }
+
+    /// Returns the value only if the cell has been written since it was at
+    /// `since_version`, so pollers can skip state they have already seen. The
+    /// version check and the reader increment are the same atomic operation.
+    ///
+    /// Any version other than `since_version` counts as newer, so this keeps
+    /// working when the version wraps or is reset. It only misses a change
+    /// if the version comes back round to exactly `since_version`.
+    #[inline]
+    pub fn get_if_newer(&self, since_version: u64) -> Option<ArcRef<T>> {
+        let (value, state) = self.pinned();
+
+        if ArcCellInner::<T>::version_of(state) != since_version {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value, as `Err` if it was set longer than `max_age` ago
+    #[inline]
+    pub fn get_fresh(&self, max_age: Duration) -> Result<ArcRef<T>, ArcRef<T>> {
+        let value = self.get();
+
+        if self.age() <= max_age {
+            Ok(value)
+        } else {
+            Err(value)
+        }
+    }
+
+    /// Time since the last write, or since creation if there hasn't been one
+    #[inline]
+    pub fn age(&self) -> Duration {
+        self.inner().age()
+    }
+
+    /// Number of `ArcCell` handles
+    #[inline]
+    pub fn strong_count(&self) -> u64 {
+        self.inner().strong_count()
+    }
+
+    /// Number of live `ArcRef`s
+    #[inline]
+    pub fn reader_count(&self) -> u64 {
+        self.inner().reader_count()
+    }
+
+    /// Whether both handles refer to the same cell
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// A hash of the cell's identity rather than its value: equal for all
+    /// clones of a cell and, while they are alive, different between cells.
+    #[inline]
+    pub fn identity_hash(&self) -> u64 {
+        hash_of(&(self.ptr.as_ptr() as usize))
+    }
+
+    #[inline]
+    pub fn downgrade(&self) -> WeakCell<T> {
+        self.inner().increment_weak();
+        WeakCell { ptr: self.ptr, phantom: PhantomData }
+    }
+
+    /// Takes a reference to the value and a future that resolves to the cell
+    /// once it has been written after this read. The version is captured by
+    /// the same atomic operation as the read, so no write can slip in between.
+    #[cfg(feature = "async")]
+    pub fn get_and_watch(&self) -> (ArcRef<T>, ChangeFuture<T>)
+    where
+        T: Send + Sync + 'static,
+    {
+        let (value, state) = self.pinned();
+        let version = ArcCellInner::<T>::version_of(state);
+        let cell = self.clone();
+
+        let changed = async move {
+            let extras = cell.inner().extras();
+            let _watching = Watching::new(&extras.watchers);
+
+            loop {
+                let notified = extras.changed.notified();
+                tokio::pin!(notified);
+                // Register before checking, so a write after the check wakes us
+                notified.as_mut().enable();
+                std::sync::atomic::fence(Ordering::SeqCst);
+
+                if cell.version() != version {
+                    return cell.get();
+                }
+
+                notified.await;
+            }
+        };
+
+        (value, ChangeFuture { inner: Box::pin(changed) })
+    }
+
+    /// Gives mutable access to the value in place if this is the only handle
+    /// and nothing is reading it, saving a copy so the edit can be aborted.
+    /// Committing counts as a write: it bumps the version and sets the dirty
+    /// bit, so cached hashes and snapshots of the old value go stale.
+    pub fn edit(&mut self) -> Option<Editor<'_, T>>
+    where
+        T: Clone,
+    {
+        if !self.inner().is_unique() {
+            return None;
+        }
+
+        let value = unsafe { &mut *(self.inner().ptr() as *mut T) };
+        let saved = Some(value.clone());
+
+        Some(Editor { cell: self, value, saved })
+    }
+
+    /// Returns an observer that re-reads the cell on demand without keeping it alive
+    #[inline]
+    pub fn subscribe(&self) -> Subscription<T> {
+        Subscription { cell: self.downgrade() }
+    }
+
+    /// Number of writes since the cell was created, wrapping after
+    /// 16,777,215 (`2^24 - 1`)
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.inner().version()
+    }
+
+    /// Atomically zeroes the version and returns the value it had, so
+    /// observers can restart their "seen since" tracking at a checkpoint.
+    /// Writes racing with the reset are counted either before it or after.
+    ///
+    /// Anything holding a version from before the reset, such as a
+    /// `RollbackToken`, a `Reservation` or the result cached by a `Derived`,
+    /// compares against the old numbering and may match a later write that
+    /// reuses the same number. The hash cached by `set_hashed` is cleared,
+    /// though a `set_hashed` racing with the reset can still store a hash
+    /// tagged with the old numbering.
+    #[inline]
+    pub fn reset_version(&self) -> u64 {
+        self.inner().reset_version()
+    }
+
+    /// Whether the value has changed since the last call, clearing the flag.
+    /// A new cell starts clean.
+    #[inline]
+    pub fn take_dirty(&self) -> bool {
+        self.inner().take_dirty()
+    }
+
+    /// Checks that the cell is at `version` and holds a value equal to `value`,
+    /// as one consistent snapshot.
+    ///
+    /// The value is compared under a reader, so a racing write cannot free it
+    /// mid-comparison, and the version is re-read afterwards, seqlock style,
+    /// so such a write makes this return `false`.
+    pub fn matches(&self, version: u64, value: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        let (pin, state) = self.pinned();
+
+        if ArcCellInner::<T>::version_of(state) != version {
+            return false;
+        }
+
+        let equal = unsafe { &*ArcCellInner::<T>::ptr_of(state) } == value;
+        drop(pin);
+
+        equal && self.inner().version() == version
+    }
+
+    #[inline]
+    fn inner(&self) -> &ArcCellInner<T> {
+        let inner = unsafe { self.ptr.as_ref() };
+        #[cfg(debug_assertions)]
+        inner.check_canary();
+        inner
+    }
+}
+
+impl<K, V, S: Default> ArcCell<HashMap<K, V, S>> {
+    /// Swaps in an empty map and returns the previous one by value
+    #[inline]
+    pub fn take_map(&self) -> HashMap<K, V, S> {
+        *self.set(Box::default())
+    }
+}
+
+#[cfg(feature = "sha2")]
+impl<T: Hash> ArcCell<Vec<T>> {
+    /// Computes a SHA-256 Merkle root over the elements of the current vec,
+    /// holding a reference to it for the duration.
+    ///
+    /// A leaf is `SHA-256(0x00 || bytes)`, where `bytes` is what the element's
+    /// `Hash` impl writes, with integers little-endian and `usize` as 64 bits.
+    /// A node is `SHA-256(0x01 || left || right)`, an odd node out moves up
+    /// unchanged, and an empty vec hashes to `SHA-256("")`. The root is the
+    /// same on every platform for elements whose `Hash` impl is; std's impls
+    /// for strings and collections add their own length or terminator bytes.
+    ///
+    /// Needs the `sha2` feature.
+    pub fn content_hash(&self) -> [u8; 32] {
+        let value = self.get();
+
+        let mut level = value
+            .iter()
+            .map(|element| {
+                Sha256::digest(|h| {
+                    h.update(&[0]);
+                    element.hash(h);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if level.is_empty() {
+            return Sha256::digest(|_| {});
+        }
+
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => Sha256::digest(|h| {
+                        h.update(&[1]);
+                        h.update(left);
+                        h.update(right);
+                    }),
+                    // An odd node out moves up unchanged
+                    [node] => *node,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+
+        level[0]
+    }
+}
+
+impl ArcCell<f64> {
+    /// Folds `sample` into the exponential moving average held by the cell,
+    /// `alpha * sample + (1 - alpha) * current`, retrying if another writer
+    /// gets in first. Returns the new average.
+    pub fn update_ema(&self, sample: f64, alpha: f64) -> f64 {
+        let mut average = 0.0;
+
+        self.update(|current| {
+            average = alpha * sample + (1.0 - alpha) * current;
+            Box::new(average)
+        });
+
+        average
+    }
+}
+
+/// Arc-backed storage: the cell holds an `Arc<T>`, so reads can hand out
+/// shared ownership of the value without copying it.
+impl<T> ArcCell<Arc<T>> {
+    /// Builds a cell holding the `Arc` produced by `f`
+    #[inline]
+    pub fn from_arc_fn<F: FnOnce() -> Arc<T>>(f: F) -> ArcCell<Arc<T>> {
+        Self::new(Box::new(f()))
+    }
+
+    /// Returns a clone of the stored `Arc`
+    #[inline]
+    pub fn get_arc(&self) -> Arc<T> {
+        Arc::clone(&self.get())
+    }
+
+    /// Replaces the stored `Arc` with `f(&current)`, retrying if another
+    /// writer gets in first. `f` can share unchanged parts of the current
+    /// value with the new one, and anyone holding the old `Arc` keeps it.
+    #[inline]
+    pub fn update_cow<F: Fn(&Arc<T>) -> Arc<T>>(&self, f: F) {
+        self.update(|current| Box::new(f(current)));
+    }
+
+    /// Installs `new` unless the cell already holds that same allocation, in
+    /// which case `new` is dropped and nothing changes. Returns whether it
+    /// was installed.
+    pub fn set_arc_dedup(&self, new: Arc<T>) -> bool {
+        let new = Box::into_raw(Box::new(new));
+
+        loop {
+            let (pin, state) = self.pinned();
+            let current = unsafe { &*ArcCellInner::ptr_of(state) };
+
+            if Arc::ptr_eq(current, unsafe { &*new }) {
+                drop(pin);
+                drop(unsafe { Box::from_raw(new) });
+                return false;
+            }
+
+            let swapped = self
+                .inner()
+                .compare_exchange_version(ArcCellInner::<Arc<T>>::version_of(state), new);
+            drop(pin);
+
+            if let Ok(old) = swapped {
+                self.retire(old);
+                return true;
+            }
+        }
+    }
+}
+
+impl<T> WeakCell<T> {
+    /// Returns a reference to the cell's value, or `None` if the cell is gone
+    #[inline]
+    pub fn upgrade(&self) -> Option<ArcRef<T>> {
+        let state = self.inner().try_acquire(ArcCellInner::<T>::ONE_READER)?;
+
+        Some(ArcRef::counted(self.ptr, state))
+    }
+
+    /// Turns this into an owning `ArcCell` handle, or `None` if the value
+    /// has already been dropped
+    #[inline]
+    pub fn into_strong(self) -> Option<ArcCell<T>> {
+        self.inner().try_acquire(ArcCellInner::<T>::ONE_STRONG)?;
+
+        Some(ArcCell::from_inner(self.ptr))
+    }
+
+    #[inline]
+    fn inner(&self) -> &ArcCellInner<T> {
+        let inner = unsafe { self.ptr.as_ref() };
+        #[cfg(debug_assertions)]
+        inner.check_canary();
+        inner
+    }
+}
+
+impl<T> Clone for WeakCell<T> {
+    fn clone(&self) -> Self {
+        self.inner().increment_weak();
+
+        Self {
+            ptr: self.ptr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> RollbackToken<'a, T> {
+    /// Keeps the new value and frees the old one once no reader can still be
+    /// using it
+    #[inline]
+    pub fn commit(self) {}
+
+    /// Restores the old value, returning the new one it displaced. If another
+    /// write has happened since, nothing changes and the old value is handed back.
+    pub fn rollback(mut self) -> Result<Box<T>, Box<T>> {
+        let old = Box::into_raw(self.old.take().expect("token already rolled back"));
+
+        match self.cell.inner().compare_exchange_version(self.version, old) {
+            Ok(new) => Ok(unsafe { Box::from_raw(new) }),
+            Err(_) => Err(unsafe { Box::from_raw(old) }),
+        }
+    }
+}
+
+impl<'a, T> Drop for RollbackToken<'a, T> {
+    fn drop(&mut self) {
+        if let Some(old) = self.old.take() {
+            self.cell.retire(Box::into_raw(old));
+        }
+    }
+}
+
+impl<'a, T> Editor<'a, T> {
+    /// Keeps the edits and publishes them as a write
+    #[inline]
+    pub fn commit(self) {}
+
+    /// Restores the value saved when the edit began, leaving the version and
+    /// dirty bit as they were
+    #[inline]
+    pub fn abort(mut self) {
+        if let Some(saved) = self.saved.take() {
+            *self.value = saved;
+        }
+    }
+}
+
+impl<'a, T> Drop for Editor<'a, T> {
+    fn drop(&mut self) {
+        if self.saved.is_some() {
+            // Reinstalling the same pointer bumps the version like any write
+            self.cell.inner().set_ptr(self.value as *mut T);
+        }
+    }
+}
+
+impl<'a, T> Deref for Editor<'a, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> DerefMut for Editor<'a, T> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl Generation {
+    /// Starts at generation `0`
+    #[inline]
+    pub fn new() -> Generation {
+        Generation(AtomicU64::new(0))
+    }
+
+    #[inline]
+    pub fn current(&self) -> u64 {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Starts the next batch, returning its number
+    #[inline]
+    pub fn advance(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::AcqRel) + 1
+    }
+}
+
+impl<T> FullSnapshot<T> {
+    #[inline]
+    pub fn value(&self) -> &ArcRef<T> {
+        &self.value
+    }
+
+    #[inline]
+    pub fn into_value(self) -> ArcRef<T> {
+        self.value
+    }
+
+    /// Number of `ArcCell` handles
+    #[inline]
+    pub fn strong_count(&self) -> u64 {
+        self.strong
+    }
+
+    /// Number of live `ArcRef`s, including the one in this snapshot
+    #[inline]
+    pub fn reader_count(&self) -> u64 {
+        self.readers
+    }
+
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// When the cell was last written, or created if it never has been
+    #[inline]
+    pub fn last_set(&self) -> Instant {
+        self.last_set
+    }
+}
+
+impl<'a, T> Reservation<'a, T> {
+    /// Whether another write has happened since the reservation was made
+    #[inline]
+    pub fn is_superseded(&self) -> bool {
+        self.cell.version() != self.version
+    }
+
+    /// Publishes `data` and returns old data, unless another write has
+    /// happened since the reservation was made, in which case `data` is
+    /// handed back and the cell is untouched
+    #[inline]
+    pub fn fill(self, data: Box<T>) -> Result<Box<T>, Box<T>> {
+        self.cell.set_at_version(self.version, data)
+    }
+}
+
+impl<'a, T> WriteTicket<'a, T> {
+    /// The version the cell was at when the write began
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Installs `data` and returns the old value if the cell is still at the
+    /// ticket's version. Otherwise `data` is handed back and nothing changes.
+    #[inline]
+    pub fn commit(self, data: Box<T>) -> Result<Box<T>, Box<T>> {
+        self.cell.set_at_version(self.version, data)
+    }
+}
+
+impl<T> Subscription<T> {
+    /// Reads the cell's current value, or `None` once the cell has been dropped
+    #[inline]
+    pub fn latest(&self) -> Option<ArcRef<T>> {
+        self.cell.upgrade()
+    }
+}
+
+impl<T, U: Clone> Derived<T, U> {
+    /// The result for the cell's current value, reusing the cached one if the
+    /// cell has not been written since it was computed
+    pub fn get(&self) -> U {
+        let (value, state) = self.cell.pinned();
+        let version = ArcCellInner::<T>::version_of(state);
+
+        if let Some((cached_version, cached)) = &*self.cache() {
+            if *cached_version == version {
+                return cached.clone();
+            }
+        }
+
+        // Computed without the lock held, so `f` may use the cell freely
+        let result = (self.f)(unsafe { &*ArcCellInner::ptr_of(state) });
+        drop(value);
+
+        *self.cache() = Some((version, result.clone()));
+        result
+    }
+
+    #[inline]
+    fn cache(&self) -> MutexGuard<'_, Option<(u64, U)>> {
+        self.cached.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> Watching<'a> {
+    #[inline]
+    fn new(watchers: &'a AtomicU64) -> Watching<'a> {
+        watchers.fetch_add(1, Ordering::Relaxed);
+        Watching(watchers)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'a> Drop for Watching<'a> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T> Future for ChangeFuture<T> {
+    type Output = ArcRef<T>;
+
+    #[inline]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<ArcRef<T>> {
+        self.inner.as_mut().poll(cx)
+    }
+}
+
+impl<T> Clone for Subscription<T> {
+    fn clone(&self) -> Self {
+        Self {
+            cell: self.cell.clone(),
+        }
+    }
+}
+
+fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl<T> ArcRef<T> {
+    /// Wraps a reader count already taken, `state` being the state it was
+    /// taken in
+    #[inline]
+    fn counted(ptr: NonNull<ArcCellInner<T>>, state: u128) -> ArcRef<T> {
+        ArcRef {
+            ptr,
+            version: ArcCellInner::<T>::version_of(state),
+            phantom: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn inner(&self) -> &ArcCellInner<T> {
+        let inner = unsafe { self.ptr.as_ref() };
+        #[cfg(debug_assertions)]
+        inner.check_canary();
+        inner
+    }
+
+    /// Narrows the reference to the part of the value picked by `f`, keeping
+    /// the whole value readable for as long as the projection lives.
+    ///
+    /// This is an associated function, called as `ArcRef::map(r, f)`, so it
+    /// does not shadow a `map` method on `T`.
+    #[inline]
+    pub fn map<U: ?Sized, F>(this: Self, f: F) -> MappedRef<T, U>
+    where
+        F: for<'a> Fn(&'a T) -> &'a U + 'static,
+    {
+        MappedRef { base: this, project: Box::new(f) }
+    }
+
+    /// Like [`map`](Self::map), for a field that is itself boxed: the
+    /// projection derefs to the box's contents.
+    #[inline]
+    pub fn map_box<U: ?Sized + 'static, F>(this: Self, f: F) -> MappedRef<T, U>
+    where
+        F: for<'a> Fn(&'a T) -> &'a Box<U> + 'static,
+    {
+        ArcRef::map(this, move |value| &**f(value))
+    }
+}
+
+impl ArcRef<Vec<u8>> {
+    /// Borrows the buffer as an `IoSlice` for vectored writes without copying.
+    /// The slice borrows this ref, so the ref keeps counting as a reader for
+    /// as long as the slice is in use. As with any deref, the buffer must not
+    /// be replaced and freed by a `set` while the slice is alive.
+    #[inline]
+    pub fn as_io_slice(&self) -> IoSlice<'_> {
+        IoSlice::new(self)
+    }
+}
+
+impl Deref for ErasedRef {
+    type Target = dyn Any + Send + Sync;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.inner.value()
+    }
+}
+
+impl<T, U: ?Sized> Deref for MappedRef<T, U> {
+    type Target = U;
+
+    #[inline]
+    fn deref(&self) -> &U {
+        (self.project)(&self.base)
+    }
+}
+
+impl<T: Display> Display for ArcRef<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(unsafe { &*self.inner().ptr() }, f)
+    }
+}
+
+impl<T: Debug> Debug for ArcRef<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(unsafe { &*self.inner().ptr() }, f)
+    }
+}
+
+impl<T> Deref for ArcRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*self.inner().ptr() }
+    }
+}
+
+impl<T> Clone for ArcCell<T> {
+    fn clone(&self) -> Self {
+        self.inner().increment_strong();
+
+        Self {
+            ptr: self.ptr,
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn big_test() {
+        let v = ArcCell::new(Box::new("Something horrible".to_string()));
+        let v0 = ArcCell::clone(&v);
+        let t = std::thread::spawn(move || {
+            println!("{}", v0.get());
+            let v1 = ArcCell::clone(&v0);
+            let _t = std::thread::spawn(move || {
+                println!("{}", v1.get());
+                v1.set(Box::new("Some other value again heh".to_string()));
+            });
+            v0.set(Box::new("Some other value".to_string()));
+        });
+        let v0 = ArcCell::clone(&v);
+        let _t = std::thread::spawn(move || {
+            println!("{}", v0.get());
+            let v1 = ArcCell::clone(&v0);
+            let _t = std::thread::spawn(move || {
                 println!("{}", v1.get());
                 v1.set(Box::new("Some other value again heh 1".to_string()));
             });
@@ -253,38 +2220,1261 @@ mod tests {
             });
             v0.set(Box::new("Some other value".to_string()));
         });
-        let v0 = ArcCell::clone(&v);
-        let _t = std::thread::spawn(move || {
-            println!("{}", v0.get());
-            let v1 = ArcCell::clone(&v0);
-            let _t = std::thread::spawn(move || {
-                println!("{}", v1.get());
-                v1.set(Box::new("Some other value again heh 4".to_string()));
-            });
-            v0.set(Box::new("Some other value".to_string()));
+        let v0 = ArcCell::clone(&v);
+        let _t = std::thread::spawn(move || {
+            println!("{}", v0.get());
+            let v1 = ArcCell::clone(&v0);
+            let _t = std::thread::spawn(move || {
+                println!("{}", v1.get());
+                v1.set(Box::new("Some other value again heh 4".to_string()));
+            });
+            v0.set(Box::new("Some other value".to_string()));
+        });
+        let v0 = ArcCell::clone(&v);
+        let _t = std::thread::spawn(move || {
+            println!("{}", v0.get());
+            let v1 = ArcCell::clone(&v0);
+            let _t = std::thread::spawn(move || {
+                println!("{}", v1.get());
+                v1.set(Box::new("Some other value again heh 5".to_string()));
+            });
+            v0.set(Box::new("Some other value".to_string()));
+        });
+        let v0 = ArcCell::clone(&v);
+        let _t = std::thread::spawn(move || {
+            println!("{}", v0.get());
+            let v1 = ArcCell::clone(&v0);
+            let _t = std::thread::spawn(move || {
+                println!("{}", v1.get());
+                v1.set(Box::new("Some other value again heh 6".to_string()));
+            });
+            v0.set(Box::new("Some other value".to_string()));
+        });
+        println!("A: {}", v.get());
+        let _ = t.join();
+        println!("B: {}", v.get());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn canary_survives_normal_use() {
+        let v = ArcCell::new(Box::new(1u32));
+        let v0 = ArcCell::clone(&v);
+
+        assert_eq!(*v.get(), 1);
+        assert_eq!(*v0.set(Box::new(2)), 1);
+        assert_eq!(*v0.get(), 2);
+        assert_eq!(v.inner().canary, ArcCellInner::<u32>::CANARY);
+
+        drop(v0);
+        assert_eq!(*v.get(), 2);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn pointer_tag_byte_survives_packing() {
+        type Inner = ArcCellInner<u8>;
+        let tagged = 0xB400_7F12_3456_7890_usize as *const u8;
+
+        assert_eq!(Inner::pack_ptr(tagged) & !Inner::MASK_PTR, 0);
+        assert_eq!(Inner::ptr_of(Inner::pack_ptr(tagged)) as *const u8, tagged);
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    #[should_panic]
+    fn pointer_above_48_bits_rejected() {
+        ArcCellInner::<u8>::pack_ptr(0x0001_0000_0000_0000_usize as *const u8);
+    }
+
+    #[test]
+    fn fold_increments() {
+        let v = ArcCell::new(Box::new(0u32));
+        v.fold(vec![1, 2, 3, 4], |current, n| Box::new(current + n));
+        assert_eq!(*v.get(), 10);
+    }
+
+    #[test]
+    fn matches_version_and_value() {
+        let v = ArcCell::new(Box::new(1u32));
+        assert_eq!(v.version(), 0);
+
+        v.set(Box::new(2));
+        assert_eq!(v.version(), 1);
+
+        assert!(v.matches(1, &2));
+        assert!(!v.matches(1, &1));
+        assert!(!v.matches(0, &2));
+        assert!(!v.matches(0, &1));
+    }
+
+    #[test]
+    fn matches_races_with_retiring_writes() {
+        let v = ArcCell::new(Box::new(0u32));
+        let writer = {
+            let v = v.clone();
+            std::thread::spawn(move || {
+                for i in 1..=100 {
+                    v.set_hashed(Box::new(i));
+                }
+            })
+        };
+
+        while v.version() < 100 {
+            let version = v.version();
+            v.matches(version, &(version as u32));
+        }
+
+        writer.join().unwrap();
+        assert!(v.matches(100, &100));
+    }
+
+    #[test]
+    fn subscription_sees_latest() {
+        let v = ArcCell::new(Box::new(0u32));
+        let sub = v.subscribe();
+        let sub0 = sub.clone();
+
+        for i in 1..=3 {
+            v.set(Box::new(i));
+            assert_eq!(*sub.latest().unwrap(), i);
+        }
+
+        let seen = std::thread::spawn(move || *sub0.latest().unwrap()).join().unwrap();
+        assert_eq!(seen, 3);
+    }
+
+    #[test]
+    fn rollback_token_commit() {
+        let v = ArcCell::new(Box::new(1u32));
+        v.set_with_rollback(Box::new(2)).commit();
+        assert_eq!(*v.get(), 2);
+
+        let old = Arc::new(3);
+        let v = ArcCell::new(Box::new(Arc::clone(&old)));
+        let reader = v.get();
+        v.set_with_rollback(Box::new(Arc::new(4))).commit();
+        assert_eq!(Arc::strong_count(&old), 2);
+
+        drop(reader);
+        drop(v);
+        assert_eq!(Arc::strong_count(&old), 1);
+    }
+
+    #[test]
+    fn rollback_token_rollback() {
+        let v = ArcCell::new(Box::new(1u32));
+        let token = v.set_with_rollback(Box::new(2));
+        assert_eq!(*v.get(), 2);
+
+        assert_eq!(*token.rollback().unwrap(), 2);
+        assert_eq!(*v.get(), 1);
+
+        let token = v.set_with_rollback(Box::new(3));
+        v.set(Box::new(4));
+        assert_eq!(*token.rollback().unwrap_err(), 1);
+        assert_eq!(*v.get(), 4);
+    }
+
+    #[test]
+    fn update_all_cells() {
+        let cells = (0..8u32).map(|i| ArcCell::new(Box::new(i))).collect::<Vec<_>>();
+        ArcCell::update_all(&cells, |n| Box::new(n + 1));
+
+        for (i, cell) in cells.iter().enumerate() {
+            assert_eq!(*cell.get(), i as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn age_resets_on_set() {
+        let v = ArcCell::new(Box::new(1u32));
+        std::thread::sleep(Duration::from_millis(20));
+
+        let before = v.age();
+        assert!(before >= Duration::from_millis(20));
+        assert!(v.get_fresh(Duration::from_millis(10)).is_err());
+
+        v.set(Box::new(2));
+        assert!(v.age() < before);
+        assert_eq!(*v.get_fresh(Duration::from_secs(60)).unwrap(), 2);
+    }
+
+    #[test]
+    fn set_reports_readers() {
+        let v = ArcCell::new(Box::new(1u32));
+        let readers = (0..3).map(|_| v.get()).collect::<Vec<_>>();
+
+        let (old, count) = v.set_report_readers(Box::new(2));
+        assert_eq!(*old, 1);
+        assert_eq!(count, 3);
+        assert_eq!(*readers[0], 2);
+
+        drop(readers);
+        assert_eq!(v.reader_count(), 0);
+        assert_eq!(v.set_report_readers(Box::new(3)).1, 0);
+    }
+
+    #[test]
+    fn from_arc_fn_shares_allocation() {
+        let original = Arc::new("shared".to_string());
+        let v = ArcCell::from_arc_fn(|| Arc::clone(&original));
+
+        assert!(Arc::ptr_eq(&v.get_arc(), &original));
+    }
+
+    #[test]
+    fn set_ordered_publishes() {
+        let v = ArcCell::new(Box::new(0u32));
+
+        for (i, order) in [Ordering::Release, Ordering::AcqRel, Ordering::SeqCst].iter().enumerate() {
+            assert_eq!(*v.set_ordered(Box::new(i as u32 + 1), *order), i as u32);
+            assert_eq!(*v.get(), i as u32 + 1);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn set_ordered_rejects_relaxed() {
+        let v = ArcCell::new(Box::new(0u32));
+        v.set_ordered(Box::new(1), Ordering::Relaxed);
+    }
+
+    #[test]
+    fn full_snapshot_is_consistent() {
+        let v = ArcCell::new(Box::new(0u64));
+        let writers = (0..2)
+            .map(|_| {
+                let v = ArcCell::clone(&v);
+                std::thread::spawn(move || {
+                    for i in 0..200 {
+                        v.set(Box::new(i));
+                        let _ = v.get();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut last = v.full_snapshot();
+        for _ in 0..200 {
+            let snapshot = v.full_snapshot();
+            assert!(snapshot.strong_count() >= 1);
+            assert!(snapshot.reader_count() >= 2);
+            assert!(snapshot.version() >= last.version());
+            assert!(snapshot.last_set() >= last.last_set());
+            last = snapshot;
+        }
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        drop(last);
+
+        let snapshot = v.full_snapshot();
+        assert_eq!(snapshot.strong_count(), 1);
+        assert_eq!(snapshot.reader_count(), 1);
+        assert_eq!(snapshot.version(), 400);
+    }
+
+    #[test]
+    fn set_sending_forwards_old_values() {
+        let v = ArcCell::new(Box::new(0u32));
+        let (tx, rx) = mpsc::channel();
+
+        for i in 1..=3 {
+            v.set_sending(Box::new(i), &tx).unwrap();
+        }
+        drop(tx);
+
+        assert_eq!(rx.iter().map(|old| *old).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(*v.get(), 3);
+    }
+
+    #[test]
+    fn set_hashed_caches_hash() {
+        let v = ArcCell::new(Box::new("one".to_string()));
+        assert_eq!(v.cached_hash(), None);
+
+        let hash = v.set_hashed(Box::new("two".to_string()));
+        assert_eq!(hash, hash_of(&"two".to_string()));
+        assert_eq!(v.cached_hash(), Some(hash));
+
+        v.set(Box::new("three".to_string()));
+        assert_eq!(v.cached_hash(), None);
+    }
+
+    #[test]
+    fn init_once_has_one_winner() {
+        let v = unsafe { ArcCell::<usize>::empty() };
+        assert!(v.is_empty());
+
+        let threads = (0..8)
+            .map(|i| {
+                let v = ArcCell::clone(&v);
+                std::thread::spawn(move || match v.init_once(Box::new(i)) {
+                    Ok(value) => (true, *value),
+                    Err(value) => (false, *value),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let results = threads.into_iter().map(|t| t.join().unwrap()).collect::<Vec<_>>();
+        let winners = results.iter().filter(|(won, _)| *won).collect::<Vec<_>>();
+
+        assert_eq!(winners.len(), 1);
+        assert!(results.iter().all(|(_, value)| *value == winners[0].1));
+        assert_eq!(*v.get(), winners[0].1);
+        assert!(!v.is_empty());
+    }
+
+    #[test]
+    fn reservation_publishes_on_fill() {
+        let v = ArcCell::new(Box::new(1u32));
+        let reader = v.get();
+
+        let reservation = v.reserve();
+        assert_eq!(*reader, 1);
+        assert!(!reservation.is_superseded());
+
+        assert_eq!(*reservation.fill(Box::new(2)).unwrap(), 1);
+        assert_eq!(*reader, 2);
+
+        let reservation = v.reserve();
+        v.set(Box::new(3));
+        assert!(reservation.is_superseded());
+        assert_eq!(*reservation.fill(Box::new(4)).unwrap_err(), 4);
+        assert_eq!(*reader, 3);
+    }
+
+    #[test]
+    fn compare_and_swap_by_hash() {
+        let v = ArcCell::new(Box::new("one".to_string()));
+
+        let wrong = hash_of(&"two".to_string());
+        assert_eq!(*v.compare_and_swap_hash(wrong, Box::new("x".to_string())).unwrap_err(), "x");
+        assert_eq!(*v.get(), "one");
+
+        let hash = hash_of(&"one".to_string());
+        assert_eq!(*v.compare_and_swap_hash(hash, Box::new("two".to_string())).unwrap(), "one");
+
+        let hash = v.set_hashed(Box::new("three".to_string()));
+        assert_eq!(*v.compare_and_swap_hash(hash, Box::new("four".to_string())).unwrap(), "three");
+        assert_eq!(*v.get(), "four");
+    }
+
+    #[test]
+    fn weak_into_strong() {
+        let v = ArcCell::new(Box::new(1u32));
+        let owner = v.downgrade().into_strong().unwrap();
+        assert_eq!(v.strong_count(), 2);
+
+        owner.set(Box::new(2));
+        assert_eq!(*v.get(), 2);
+
+        let weak = v.downgrade();
+        drop(v);
+        drop(owner);
+        assert!(weak.clone().into_strong().is_none());
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn set_notify_wakes_waiters() {
+        let notify = Arc::new(tokio::sync::Notify::new());
+        let v = ArcCell::new(Box::new(1u32));
+
+        let waiter = {
+            let notify = Arc::clone(&notify);
+            let v = ArcCell::clone(&v);
+            tokio::spawn(async move {
+                notify.notified().await;
+                *v.get()
+            })
+        };
+
+        // Let the task register with `notify` before writing
+        tokio::task::yield_now().await;
+        v.set_notify(Box::new(2), &notify);
+
+        assert_eq!(waiter.await.unwrap(), 2);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn get_and_watch_sees_next_write() {
+        let v = ArcCell::new(Box::new(1u32));
+
+        // A write between the read and the await is not missed
+        let (current, changed) = v.get_and_watch();
+        assert_eq!(*current, 1);
+        v.set(Box::new(2));
+        assert_eq!(*changed.await, 2);
+
+        let (_, changed) = v.get_and_watch();
+        let waiter = tokio::spawn(async move { *changed.await });
+
+        tokio::task::yield_now().await;
+        v.set(Box::new(3));
+
+        assert_eq!(waiter.await.unwrap(), 3);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn watchers_counted_while_pending() {
+        let v = ArcCell::new(Box::new(1u32));
+        let watchers = || v.inner().extras().watchers.load(Ordering::SeqCst);
+
+        let (_, changed) = v.get_and_watch();
+        let waiter = tokio::spawn(async move { *changed.await });
+        tokio::task::yield_now().await;
+        assert_eq!(watchers(), 1);
+
+        v.set(Box::new(2));
+        assert_eq!(waiter.await.unwrap(), 2);
+        assert_eq!(watchers(), 0);
+
+        let (_, changed) = v.get_and_watch();
+        let waiter = tokio::spawn(async move { drop(changed.await) });
+        tokio::task::yield_now().await;
+        waiter.abort();
+        assert!(waiter.await.is_err());
+        assert_eq!(watchers(), 0);
+    }
+
+    #[test]
+    fn get_if_newer_polls() {
+        let v = ArcCell::new(Box::new(1u32));
+        let seen = v.version();
+        assert!(v.get_if_newer(seen).is_none());
+        assert_eq!(v.reader_count(), 0);
+
+        v.set(Box::new(2));
+        assert_eq!(*v.get_if_newer(seen).unwrap(), 2);
+        assert!(v.get_if_newer(v.version()).is_none());
+
+        let seen = v.version();
+        v.reset_version();
+        assert_eq!(*v.get_if_newer(seen).unwrap(), 2);
+    }
+
+    #[test]
+    fn reset_version_restarts_count() {
+        let v = ArcCell::new(Box::new(0u32));
+        for i in 1..=3 {
+            v.set(Box::new(i));
+        }
+
+        assert_eq!(v.reset_version(), 3);
+        assert_eq!(v.version(), 0);
+
+        v.set(Box::new(4));
+        v.set(Box::new(5));
+        assert_eq!(v.version(), 2);
+        assert_eq!(*v.get(), 5);
+    }
+
+    #[test]
+    fn swap_into_stack_slot() {
+        let v = ArcCell::new(Box::new("old".to_string()));
+        let mut out = MaybeUninit::uninit();
+
+        unsafe { v.swap_into(Box::new("new".to_string()), &mut out) };
+
+        assert_eq!(unsafe { out.assume_init() }, "old");
+        assert_eq!(*v.get(), "new");
+    }
+
+    #[test]
+    fn set_timed_swaps() {
+        let v = ArcCell::new(Box::new(1u32));
+        let (old, elapsed) = v.set_timed(Box::new(2));
+
+        assert_eq!(*old, 1);
+        assert_eq!(*v.get(), 2);
+        assert!(elapsed < Duration::from_secs(60));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn set_traced_records_old_and_new() {
+        use tracing::{field::Field, span, Event, Metadata};
+
+        #[derive(Clone, Default)]
+        struct Recorder(Arc<Mutex<Vec<(&'static str, String)>>>);
+
+        impl tracing::field::Visit for Recorder {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                self.0.lock().unwrap().push((field.name(), format!("{:?}", value)));
+            }
+        }
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                event.record(&mut self.clone());
+            }
+
+            fn enter(&self, _: &span::Id) {}
+
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let recorder = Recorder::default();
+        let v = ArcCell::new(Box::new("before"));
+
+        tracing::subscriber::with_default(recorder.clone(), || {
+            assert_eq!(*v.set_traced(Box::new("after")), "before");
+        });
+
+        let fields = recorder.0.lock().unwrap();
+        assert!(fields.contains(&("old", "\"before\"".to_string())));
+        assert!(fields.contains(&("new", "\"after\"".to_string())));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn to_json_serializes_value() {
+        #[derive(serde::Serialize)]
+        struct Config {
+            name: String,
+            retries: u32,
+        }
+
+        let v = ArcCell::new(Box::new(Config {
+            name: "primary".to_string(),
+            retries: 3,
+        }));
+
+        let json = v.to_json().unwrap();
+        assert_eq!(json["name"], "primary");
+        assert_eq!(json["retries"], 3);
+    }
+
+    #[test]
+    fn compare_and_swap_or_else_succeeds_first_time() {
+        let v = ArcCell::new(Box::new(1u32));
+        let current = v.get();
+
+        let old = v.compare_and_swap_or_else(&current, Box::new(2), |_| unreachable!());
+        assert_eq!(*old, 1);
+        assert_eq!(*v.get(), 2);
+    }
+
+    #[test]
+    fn compare_and_swap_or_else_recovers() {
+        let v = ArcCell::new(Box::new(1u32));
+        let reader = v.get();
+
+        // Another writer gets in first
+        drop(v.set(Box::new(5)));
+
+        let mut calls = 0;
+        let old = v.compare_and_swap_or_else(&reader, Box::new(2), |current| {
+            calls += 1;
+            Box::new(current + 10)
+        });
+
+        assert_eq!(*old, 5);
+        assert_eq!(calls, 1);
+        assert_eq!(*v.get(), 15);
+    }
+
+    #[test]
+    fn compare_and_swap_or_else_sees_reinstalled_value() {
+        let v = ArcCell::new(Box::new(1u32));
+        let reader = v.get();
+
+        // Same allocation back in place, but two writes later
+        let old = v.set(Box::new(5));
+        drop(v.set(old));
+
+        let mut calls = 0;
+        let old = v.compare_and_swap_or_else(&reader, Box::new(2), |current| {
+            calls += 1;
+            Box::new(current + 10)
         });
+
+        assert_eq!(*old, 1);
+        assert_eq!(calls, 1);
+        assert_eq!(*v.get(), 11);
+    }
+
+    #[test]
+    fn identity_hash_follows_cell() {
+        let v = ArcCell::new(Box::new(1u32));
         let v0 = ArcCell::clone(&v);
-        let _t = std::thread::spawn(move || {
-            println!("{}", v0.get());
-            let v1 = ArcCell::clone(&v0);
-            let _t = std::thread::spawn(move || {
-                println!("{}", v1.get());
-                v1.set(Box::new("Some other value again heh 5".to_string()));
-            });
-            v0.set(Box::new("Some other value".to_string()));
+        let other = ArcCell::new(Box::new(1u32));
+
+        assert!(ArcCell::ptr_eq(&v, &v0));
+        assert!(!ArcCell::ptr_eq(&v, &other));
+        assert_eq!(v.identity_hash(), v0.identity_hash());
+        assert_ne!(v.identity_hash(), other.identity_hash());
+    }
+
+    #[test]
+    fn replace_from_iter_rebuilds() {
+        let v = ArcCell::new(Box::new(vec![1, 2, 3]));
+
+        let old = v.replace_from_iter(10..15);
+        assert_eq!(old, vec![1, 2, 3]);
+        assert_eq!(*v.get(), vec![10, 11, 12, 13, 14]);
+    }
+
+    #[test]
+    fn update_cow_shares_structure() {
+        struct Tree {
+            left: Arc<Vec<u32>>,
+            right: Arc<Vec<u32>>,
+        }
+
+        let v = ArcCell::new(Box::new(Arc::new(Tree {
+            left: Arc::new(vec![1, 2]),
+            right: Arc::new(vec![3, 4]),
+        })));
+        let old = v.get_arc();
+
+        v.update_cow(|tree| {
+            Arc::new(Tree {
+                left: Arc::clone(&tree.left),
+                right: Arc::new(vec![5, 6]),
+            })
         });
-        let v0 = ArcCell::clone(&v);
-        let _t = std::thread::spawn(move || {
-            println!("{}", v0.get());
-            let v1 = ArcCell::clone(&v0);
-            let _t = std::thread::spawn(move || {
-                println!("{}", v1.get());
-                v1.set(Box::new("Some other value again heh 6".to_string()));
-            });
-            v0.set(Box::new("Some other value".to_string()));
+
+        let new = v.get_arc();
+        assert!(Arc::ptr_eq(&old.left, &new.left));
+        assert_eq!(*old.right, vec![3, 4]);
+        assert_eq!(*new.right, vec![5, 6]);
+        assert_eq!(Arc::strong_count(&old), 1);
+    }
+
+    #[test]
+    fn get_load_tracks_readers() {
+        let v = ArcCell::new(Box::new(1));
+
+        let (a, load) = v.get_load();
+        assert_eq!(load, 1);
+
+        let b = v.get();
+        let (c, load) = v.get_load();
+        assert_eq!(load, 3);
+
+        drop(a);
+        drop(b);
+        drop(c);
+        assert_eq!(v.get_load().1, 1);
+    }
+
+    #[test]
+    fn set_validated() {
+        let v = ArcCell::new(Box::new(1));
+        let positive = |x: &i32| if *x > 0 { Ok(()) } else { Err("not positive") };
+
+        assert_eq!(*v.set_validated(Box::new(2), positive).unwrap(), 1);
+        assert_eq!(*v.get(), 2);
+
+        let (rejected, e) = v.set_validated(Box::new(-1), positive).unwrap_err();
+        assert_eq!(*rejected, -1);
+        assert_eq!(e, "not positive");
+        assert_eq!(*v.get(), 2);
+    }
+
+    #[test]
+    fn set_debounced() {
+        let interval = Duration::from_millis(100);
+        let v = ArcCell::new(Box::new(0));
+        assert_eq!(*v.set_debounced(Box::new(1), interval).unwrap_err(), 1);
+
+        std::thread::sleep(interval);
+        let accepted = (0..1000)
+            .filter(|&i| v.set_debounced(Box::new(i), interval).is_ok())
+            .count();
+
+        assert!(accepted >= 1);
+        assert!(accepted < 10);
+    }
+
+    #[test]
+    fn take_dirty() {
+        let v = ArcCell::new(Box::new(0));
+        assert!(!v.take_dirty());
+
+        v.set(Box::new(1));
+        v.set(Box::new(2));
+        assert!(v.take_dirty());
+        assert!(!v.take_dirty());
+        assert_eq!(*v.get(), 2);
+
+        v.set(Box::new(3));
+        assert!(v.take_dirty());
+    }
+
+    #[test]
+    fn swap_lazy_default_calls_once() {
+        let v = ArcCell::new(Box::new(1));
+        let mut calls = 0;
+
+        let old = v.swap_lazy_default(|| {
+            calls += 1;
+            Box::new(2)
         });
-        println!("A: {}", v.get());
-        let _ = t.join();
-        println!("B: {}", v.get());
+
+        assert_eq!(calls, 1);
+        assert_eq!(*old, 1);
+        assert_eq!(*v.get(), 2);
+    }
+
+    #[test]
+    fn set_backpressured() {
+        let v = ArcCell::new(Box::new(0));
+        let readers = (0..3).map(|_| v.get()).collect::<Vec<_>>();
+
+        assert_eq!(*v.set_backpressured(Box::new(1), 2).unwrap_err(), 1);
+        assert_eq!(*v.get(), 0);
+
+        drop(readers);
+        assert_eq!(*v.set_backpressured(Box::new(2), 2).unwrap(), 0);
+        assert_eq!(*v.get(), 2);
+    }
+
+    #[test]
+    fn edit_commit_and_abort() {
+        let mut v = ArcCell::new(Box::new(vec![1, 2]));
+
+        let mut editor = v.edit().unwrap();
+        editor.push(3);
+        editor.commit();
+        assert_eq!(*v.get(), vec![1, 2, 3]);
+
+        let mut editor = v.edit().unwrap();
+        editor.clear();
+        editor.abort();
+        assert_eq!(*v.get(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn edit_counts_as_write() {
+        let mut v = ArcCell::new(Box::new(1));
+        let hash = v.set_hashed(Box::new(2));
+        assert!(v.take_dirty());
+
+        v.edit().unwrap().abort();
+        assert_eq!(v.version(), 1);
+        assert!(!v.take_dirty());
+        assert_eq!(v.cached_hash(), Some(hash));
+
+        *v.edit().unwrap() = 99;
+        assert_eq!(v.version(), 2);
+        assert!(v.take_dirty());
+        assert_eq!(v.cached_hash(), None);
+        assert!(v.compare_and_swap_hash(hash, Box::new(3)).is_err());
+        assert!(v.get_if_newer(1).is_some());
+    }
+
+    #[test]
+    fn edit_requires_unique() {
+        let mut v = ArcCell::new(Box::new(1));
+
+        let reader = v.get();
+        assert!(v.edit().is_none());
+        drop(reader);
+
+        let other = v.clone();
+        assert!(v.edit().is_none());
+        drop(other);
+
+        let weak = v.downgrade();
+        assert!(v.edit().is_none());
+        drop(weak);
+
+        *v.edit().unwrap() = 2;
+        assert_eq!(*v.get(), 2);
+    }
+
+    #[test]
+    fn update_tracked_stamps_generation() {
+        let gen = Generation::new();
+        let cells = (0..3).map(|i| ArcCell::new(Box::new(i))).collect::<Vec<_>>();
+
+        gen.advance();
+        cells[0].update_tracked(&gen, Box::new(10));
+        cells[1].update_tracked(&gen, Box::new(11));
+
+        assert_eq!(gen.advance(), 2);
+        cells[1].update_tracked(&gen, Box::new(21));
+        cells[2].set(Box::new(22));
+
+        let stamps = cells.iter().map(ArcCell::generation).collect::<Vec<_>>();
+        assert_eq!(stamps, vec![1, 2, 0]);
+    }
+
+    #[test]
+    fn set_first_detects_first_write() {
+        let v = ArcCell::new(Box::new(String::new()));
+
+        let (_, first) = v.set_first(Box::new("a".to_string()), String::is_empty);
+        assert!(first);
+
+        let (old, first) = v.set_first(Box::new("b".to_string()), String::is_empty);
+        assert!(!first);
+        assert_eq!(*old, "a");
+    }
+
+    #[test]
+    fn get_with_pairs_context() {
+        let v = ArcCell::new(Box::new(40));
+
+        let total = Some(v.get_with(2)).map(|(value, ctx)| *value + ctx);
+        assert_eq!(total, Some(42));
+        assert_eq!(v.reader_count(), 0);
+    }
+
+    #[test]
+    fn update_ema_concurrent() {
+        let v = ArcCell::new(Box::new(5.0));
+
+        let threads = (0..4)
+            .map(|t| {
+                let v = v.clone();
+                std::thread::spawn(move || {
+                    for i in 0..1000 {
+                        let average = v.update_ema(((t * 1000 + i) % 11) as f64, 0.1);
+                        assert!((0.0..=10.0).contains(&average));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!((0.0..=10.0).contains(&*v.get()));
+        assert_eq!(v.update_ema(10.0, 1.0), 10.0);
+    }
+
+    #[test]
+    fn derived_memoizes_per_version() {
+        let v = ArcCell::new(Box::new(vec![1, 2, 3]));
+        let calls = Arc::new(AtomicU64::new(0));
+
+        let counted = Arc::clone(&calls);
+        let sum = v.derived(move |xs: &Vec<u32>| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            xs.iter().sum::<u32>()
+        });
+
+        assert_eq!(sum.get(), 6);
+        assert_eq!(sum.get(), 6);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        v.set(Box::new(vec![4, 5]));
+        assert_eq!(sum.get(), 9);
+        assert_eq!(sum.get(), 9);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn derived_handles_cache_separately() {
+        let v = ArcCell::new(Box::new(3u32));
+        let scaled = |factor: u32| v.derived(move |x: &u32| x * factor);
+
+        let double = scaled(2);
+        let triple = scaled(3);
+        assert_eq!(double.get(), 6);
+        assert_eq!(triple.get(), 9);
+        assert_eq!(double.get(), 6);
+    }
+
+    #[test]
+    fn undo_in_order() {
+        let v = ArcCell::new(Box::new(1));
+        for i in 2..=4 {
+            v.set_undoable(Box::new(i), 2);
+        }
+
+        assert!(v.undo());
+        assert_eq!(*v.get(), 3);
+        assert!(v.undo());
+        assert_eq!(*v.get(), 2);
+        assert!(!v.undo());
+        assert_eq!(*v.get(), 2);
+
+        v.set_undoable(Box::new(5), 0);
+        assert!(!v.undo());
+        assert_eq!(*v.get(), 5);
+    }
+
+    #[test]
+    fn undo_history_dropped_with_cell() {
+        let old = Arc::new(0);
+        let v = ArcCell::new(Box::new(Arc::clone(&old)));
+        let subscription = v.subscribe();
+
+        v.set_undoable(Box::new(Arc::new(1)), 4);
+        assert_eq!(Arc::strong_count(&old), 2);
+
+        drop(v);
+        assert_eq!(Arc::strong_count(&old), 1);
+        assert!(subscription.latest().is_none());
+    }
+
+    #[test]
+    fn undo_after_other_write() {
+        let v = ArcCell::new(Box::new(1));
+        v.set_undoable(Box::new(2), 4);
+        v.set(Box::new(3));
+
+        assert!(!v.undo());
+        assert_eq!(*v.get(), 3);
+
+        v.set_undoable(Box::new(4), 4);
+        assert!(v.undo());
+        assert_eq!(*v.get(), 3);
+        assert!(!v.undo());
+    }
+
+    #[test]
+    fn get_checked_holds() {
+        let v = ArcCell::new(Box::new(2));
+        assert_eq!(*v.get_checked(|x| x % 2 == 0), 2);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "invariant")]
+    fn get_checked_violated() {
+        let v = ArcCell::new(Box::new(3));
+        v.get_checked(|x| x % 2 == 0);
+    }
+
+    #[test]
+    fn write_ticket_commits() {
+        let v = ArcCell::new(Box::new(1));
+        let ticket = v.begin_write();
+
+        assert_eq!(*ticket.commit(Box::new(2)).unwrap(), 1);
+        assert_eq!(*v.get(), 2);
+    }
+
+    #[test]
+    fn stale_write_ticket_rejected() {
+        let v = ArcCell::new(Box::new(1));
+        let ticket = v.begin_write();
+        v.set(Box::new(2));
+
+        assert_eq!(*ticket.commit(Box::new(3)).unwrap_err(), 3);
+        assert_eq!(*v.get(), 2);
+    }
+
+    #[test]
+    fn map_projects_fields() {
+        struct Config {
+            name: String,
+            limits: Box<[u32]>,
+        }
+
+        fn total(limits: &[u32]) -> u32 {
+            limits.iter().sum()
+        }
+
+        let v = ArcCell::new(Box::new(Config {
+            name: "a".to_string(),
+            limits: Box::new([1, 2, 3]),
+        }));
+
+        let name = ArcRef::map(v.get(), |config| &config.name);
+        let limits = ArcRef::map_box(v.get(), |config| &config.limits);
+        assert_eq!(*name, "a");
+        assert_eq!(total(&limits), 6);
+        assert_eq!(v.reader_count(), 2);
+
+        drop(name);
+        drop(limits);
+        assert_eq!(v.reader_count(), 0);
+    }
+
+    #[test]
+    fn set_fingerprinted_oscillating() {
+        let v = ArcCell::new(Box::new(0u64));
+
+        let new = (0..10)
+            .map(|i| v.set_fingerprinted(Box::new(i % 2 + 1), |x| *x))
+            .collect::<Vec<_>>();
+
+        assert_eq!(new[..2], [true, true]);
+        assert!(new[2..].iter().all(|&new| !new));
+        assert!(v.set_fingerprinted(Box::new(3), |x| *x));
+    }
+
+    #[test]
+    fn set_arc_dedup() {
+        let first = Arc::new(1);
+        let v = ArcCell::from_arc_fn(|| Arc::clone(&first));
+        let version = v.version();
+
+        assert!(!v.set_arc_dedup(Arc::clone(&first)));
+        assert_eq!(v.version(), version);
+        assert_eq!(Arc::strong_count(&first), 2);
+
+        assert!(v.set_arc_dedup(Arc::new(1)));
+        assert!(!Arc::ptr_eq(&v.get_arc(), &first));
+        assert_eq!(Arc::strong_count(&first), 1);
+    }
+
+    #[test]
+    fn set_until_outlasts_interference() {
+        let v = ArcCell::new(Box::new(1u64));
+        let barrier = Arc::new(std::sync::Barrier::new(2));
+
+        let interferer = {
+            let v = v.clone();
+            let barrier = Arc::clone(&barrier);
+            std::thread::spawn(move || {
+                barrier.wait();
+                for i in 0..1000 {
+                    v.set(Box::new(i * 2 + 1));
+                }
+            })
+        };
+
+        barrier.wait();
+        let mut attempts = 0;
+        let mut next = 0;
+        v.set_until(
+            || {
+                attempts += 1;
+                next += 2;
+                Box::new(next)
+            },
+            |x| x % 2 == 0,
+        );
+        assert!(attempts >= 1);
+
+        interferer.join().unwrap();
+        v.set_until(|| Box::new(2), |x| *x == 2);
+        assert_eq!(*v.get(), 2);
+    }
+
+    #[test]
+    fn erase_and_downcast() {
+        let number = ArcCell::new(Box::new(1u32));
+        let name = ArcCell::new(Box::new("a".to_string()));
+        let flags = ArcCell::new(Box::new(vec![true]));
+
+        let reads = vec![number.erase(), name.erase(), flags.erase()];
+        assert_eq!(reads[0].downcast_ref::<u32>(), Some(&1));
+        assert_eq!(reads[1].downcast_ref::<String>().unwrap(), "a");
+        assert_eq!(reads[2].downcast_ref::<Vec<bool>>(), Some(&vec![true]));
+        assert!(reads[0].downcast_ref::<String>().is_none());
+
+        assert_eq!(number.reader_count(), 1);
+        drop(reads);
+        assert_eq!(number.reader_count(), 0);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn content_hash_tracks_contents() {
+        let v = ArcCell::new(Box::new(vec![1, 2, 3]));
+        let hash = v.content_hash();
+        assert_eq!(v.content_hash(), hash);
+
+        v.set(Box::new(vec![1, 2, 4]));
+        assert_ne!(v.content_hash(), hash);
+
+        v.set(Box::new(vec![1, 2, 3]));
+        assert_eq!(v.content_hash(), hash);
+
+        v.set(Box::new(vec![]));
+        assert_ne!(v.content_hash(), hash);
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn content_hash_is_specified() {
+        let hex = |root: [u8; 32]| root.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+
+        let v = ArcCell::new(Box::new(vec![1u32, 2, 3]));
+        assert_eq!(
+            hex(v.content_hash()),
+            "be1d534a51ccd039d097218a4a6ea4cf1cf8b69a0f85e012a72810d8930e7517"
+        );
+
+        v.set(Box::new(vec![]));
+        assert_eq!(
+            hex(v.content_hash()),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn as_io_slice_writes_buffer() {
+        use std::io::Write;
+
+        let v = ArcCell::new(Box::new(b"hello".to_vec()));
+        let mut out = Vec::new();
+
+        let buffer = v.get();
+        let written = out
+            .write_vectored(&[buffer.as_io_slice(), IoSlice::new(b" world")])
+            .unwrap();
+
+        assert_eq!(written, 11);
+        assert_eq!(out, b"hello world");
+    }
+
+    #[test]
+    fn set_max_keeps_maximum() {
+        let v = ArcCell::new(Box::new(0u64));
+        let max = Arc::new(AtomicU64::new(0));
+
+        let threads = (0..4u64)
+            .map(|t| {
+                let v = v.clone();
+                let max = Arc::clone(&max);
+                std::thread::spawn(move || {
+                    let mut x = t + 1;
+                    for _ in 0..1000 {
+                        x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                        let sample = x >> 40;
+                        max.fetch_max(sample, Ordering::SeqCst);
+                        v.set_max(Box::new(sample), u64::cmp);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*v.get(), max.load(Ordering::SeqCst));
+        assert!(!v.set_max(Box::new(0), u64::cmp));
+    }
+
+    #[test]
+    fn shared_snapshot_shares_while_held() {
+        let v = ArcCell::new(Box::new(vec![1, 2]));
+
+        let a = v.shared_snapshot();
+        let b = v.shared_snapshot();
+        assert!(Arc::ptr_eq(&a, &b));
+
+        v.set(Box::new(vec![3]));
+        let c = v.shared_snapshot();
+        assert!(!Arc::ptr_eq(&a, &c));
+        assert_eq!(*a, vec![1, 2]);
+        assert_eq!(*c, vec![3]);
+
+        drop(c);
+        let d = v.shared_snapshot();
+        assert_eq!(*d, vec![3]);
+        assert!(Arc::ptr_eq(&d, &v.shared_snapshot()));
+    }
+
+    #[test]
+    fn concurrent_folds_apply_every_step() {
+        let v = ArcCell::new(Box::new(vec![0u64]));
+
+        let threads = (0..4)
+            .map(|_| {
+                let v = v.clone();
+                std::thread::spawn(move || {
+                    v.fold(0..1000, |current, _| Box::new(vec![current[0] + 1]));
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(*v.get(), vec![4000]);
+    }
+
+    #[test]
+    fn retired_values_outlive_readers() {
+        use std::sync::atomic::AtomicUsize;
+
+        struct Counted(Arc<AtomicUsize>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let v = ArcCell::new(Box::new(Counted(Arc::clone(&dropped))));
+
+        let reader = v.get();
+        let counted = Arc::clone(&dropped);
+        v.fold([()], |_, ()| Box::new(Counted(Arc::clone(&counted))));
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+        drop(reader);
+        v.fold([()], |_, ()| Box::new(Counted(Arc::clone(&counted))));
+        assert_eq!(dropped.load(Ordering::SeqCst), 2);
+
+        let reader = v.get();
+        v.fold([()], |_, ()| Box::new(Counted(Arc::clone(&counted))));
+        drop(v);
+        assert_eq!(dropped.load(Ordering::SeqCst), 2);
+
+        drop(reader);
+        assert_eq!(dropped.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn retired_values_wait_for_long_lived_reader() {
+        let old = Arc::new(0);
+        let v = ArcCell::new(Box::new(Arc::clone(&old)));
+
+        let reader = v.get();
+        for _ in 0..1000 {
+            v.update_cow(|_| Arc::clone(&old));
+        }
+        assert_eq!(Arc::strong_count(&old), 1002);
+
+        drop(reader);
+        v.update_cow(|_| Arc::new(1));
+        assert_eq!(Arc::strong_count(&old), 1);
+    }
+
+    #[test]
+    fn extras_allocated_on_first_use() {
+        let v = ArcCell::new(Box::new(1u32));
+        drop(v.set(Box::new(2)));
+        assert_eq!(v.generation(), 0);
+        assert_eq!(v.cached_hash(), None);
+        assert!(v.inner().extras_if_any().is_none());
+
+        v.set_hashed(Box::new(3));
+        assert!(v.inner().extras_if_any().is_some());
+        assert!(v.cached_hash().is_some());
+    }
+
+    #[test]
+    fn take_map() {
+        let map = (0..10).map(|i| (i, i * 2)).collect::<HashMap<_, _>>();
+        let v = ArcCell::new(Box::new(map));
+
+        let taken = v.take_map();
+        assert_eq!(taken.len(), 10);
+        assert_eq!(taken[&3], 6);
+        assert!(v.get().is_empty());
     }
 }
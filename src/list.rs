@@ -0,0 +1,91 @@
+use std::mem::ManuallyDrop;
+use std::ptr::NonNull;
+
+use crate::{ArcCell, ArcCellInner};
+
+/// A singly-linked list node for building lock-free stacks on an
+/// `ArcCell<Option<Box<Node<T>>>>` head, see [`ArcCell::pop_front`].
+pub struct Node<T> {
+    pub value: T,
+    pub next: Option<Box<Node<T>>>,
+}
+
+/// Frees a popped head whose value and `next` have been moved out, without
+/// dropping either
+unsafe fn free_popped<T>(head: *mut Option<Box<Node<T>>>) {
+    // `ManuallyDrop` is transparent, so this is the same layout
+    let head = Box::from_raw(head as *mut Option<Box<ManuallyDrop<Node<T>>>>);
+    drop(head);
+}
+
+impl<T> ArcCell<Option<Box<Node<T>>>> {
+    /// Advances the head to the next node and returns the popped value, or
+    /// `None` if the list is empty.
+    ///
+    /// The head is read under a reader and the version is part of the
+    /// compare-exchange, so a head that was popped and whose address was
+    /// reused by another node is never mistaken for the one that was read.
+    /// The popped node is only freed once no reader can still be looking at
+    /// it, though its value is handed over straight away, as with `set`.
+    pub fn pop_front(&self) -> Option<T> {
+        loop {
+            let (pin, state) = self.pinned();
+            let head = ArcCellInner::<Option<Box<Node<T>>>>::ptr_of(state);
+            let node = unsafe { &*head }.as_ref()?;
+
+            // Other poppers may be reading the same `next`, so it is copied
+            // as a raw pointer rather than as a second owning `Box`. The new
+            // head only becomes an `Option<Box<_>>`, which has the same
+            // layout, once the swap has made it the one live copy.
+            let next = unsafe { std::ptr::read(&node.next as *const _ as *const Option<NonNull<Node<T>>>) };
+            let next = Box::into_raw(Box::new(next)) as *mut Option<Box<Node<T>>>;
+            let version = ArcCellInner::<Option<Box<Node<T>>>>::version_of(state);
+
+            match self.inner().compare_exchange_version(version, next) {
+                Ok(old) => {
+                    let value = unsafe { std::ptr::read(&node.value) };
+                    drop(pin);
+
+                    self.inner().retire(old, Some(free_popped::<T>));
+                    self.inner().reclaim(drop);
+
+                    return Some(value);
+                }
+                Err(_) => drop(unsafe { Box::from_raw(next as *mut Option<NonNull<Node<T>>>) }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_pops_take_each_once() {
+        let head = (0..1000).rev().fold(None, |next, value| Some(Box::new(Node { value, next })));
+        let list = ArcCell::new(Box::new(head));
+
+        let threads = (0..4)
+            .map(|_| {
+                let list = ArcCell::clone(&list);
+                std::thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    while let Some(value) = list.pop_front() {
+                        popped.push(value);
+                    }
+                    popped
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut popped = threads
+            .into_iter()
+            .flat_map(|t| t.join().unwrap())
+            .collect::<Vec<_>>();
+        popped.sort_unstable();
+
+        assert_eq!(popped, (0..1000).collect::<Vec<_>>());
+        assert!(list.pop_front().is_none());
+    }
+}
@@ -0,0 +1,143 @@
+use std::sync::Arc;
+
+use crate::{ArcCell, ArcCellInner, ArcRef};
+
+/// Reusable value storage for a [`PooledCell`], see [`ArcCell::with_pool`].
+pub trait Pool<T> {
+    /// Returns a box holding `value`, reusing a released allocation if one is available.
+    fn acquire(&self, value: T) -> Box<T>;
+
+    /// Takes back a box that has been swapped out of the cell.
+    fn release(&self, data: Box<T>);
+}
+
+/// A cell whose values are allocated from and returned to a [`Pool`].
+///
+/// A box swapped out by [`set`](Self::set) is only released once no reader
+/// can still be looking at it, so the pool never hands out a box that is
+/// being read. Until then it waits in the cell; if the cell always has a
+/// reader live, boxes stop coming back and `acquire` has to allocate.
+pub struct PooledCell<T> {
+    cell: ArcCell<T>,
+    pool: Arc<dyn Pool<T> + Send + Sync>,
+}
+
+impl<T> PooledCell<T> {
+    #[inline]
+    pub fn get(&self) -> ArcRef<T> {
+        self.cell.get()
+    }
+
+    /// Installs `value` in a box acquired from the pool. The old box is
+    /// released back to it once no reader can still be using it.
+    pub fn set(&self, value: T) {
+        let old = self.cell.inner().set_ptr(Box::into_raw(self.pool.acquire(value)));
+
+        self.cell.inner().retire(ArcCellInner::ptr_of(old), None);
+        self.cell.inner().reclaim(|data| self.pool.release(data));
+    }
+}
+
+impl<T> Clone for PooledCell<T> {
+    fn clone(&self) -> Self {
+        Self {
+            cell: self.cell.clone(),
+            pool: Arc::clone(&self.pool),
+        }
+    }
+}
+
+impl<T> ArcCell<T> {
+    /// Creates a cell whose values are allocated from and returned to `pool`.
+    /// Boxes still waiting for readers when the last handle and reader drop
+    /// are dropped rather than released.
+    #[inline]
+    pub fn with_pool<P: Pool<T> + Send + Sync + 'static>(data: Box<T>, pool: P) -> PooledCell<T> {
+        PooledCell {
+            cell: ArcCell::new(data),
+            pool: Arc::new(pool),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn pool_bounds_allocations() {
+        use std::sync::{atomic::AtomicUsize, Mutex};
+
+        #[derive(Default)]
+        #[allow(clippy::vec_box)]
+        struct Stats {
+            free: Mutex<Vec<Box<u64>>>,
+            allocations: AtomicUsize,
+        }
+
+        struct CountingPool(Arc<Stats>);
+
+        impl Pool<u64> for CountingPool {
+            fn acquire(&self, value: u64) -> Box<u64> {
+                match self.0.free.lock().unwrap().pop() {
+                    Some(mut data) => {
+                        *data = value;
+                        data
+                    }
+                    None => {
+                        self.0.allocations.fetch_add(1, Ordering::Relaxed);
+                        Box::new(value)
+                    }
+                }
+            }
+
+            fn release(&self, data: Box<u64>) {
+                self.0.free.lock().unwrap().push(data);
+            }
+        }
+
+        let stats = Arc::new(Stats::default());
+        let v = ArcCell::with_pool(Box::new(0), CountingPool(Arc::clone(&stats)));
+
+        for i in 1..=1000 {
+            v.set(i);
+        }
+
+        assert_eq!(*v.get(), 1000);
+        assert_eq!(stats.allocations.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.free.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn release_waits_for_readers() {
+        use std::sync::Mutex;
+
+        #[allow(clippy::vec_box)]
+        struct Released(Arc<Mutex<Vec<Box<u64>>>>);
+
+        impl Pool<u64> for Released {
+            fn acquire(&self, value: u64) -> Box<u64> {
+                Box::new(value)
+            }
+
+            fn release(&self, data: Box<u64>) {
+                self.0.lock().unwrap().push(data);
+            }
+        }
+
+        let released = Arc::new(Mutex::new(Vec::new()));
+        let v = ArcCell::with_pool(Box::new(0), Released(Arc::clone(&released)));
+
+        let reader = v.get();
+        v.set(1);
+        v.set(2);
+        assert!(released.lock().unwrap().is_empty());
+
+        drop(reader);
+        v.set(3);
+        let mut values = released.lock().unwrap().iter().map(|data| **data).collect::<Vec<_>>();
+        values.sort_unstable();
+        assert_eq!(values, vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+
+use crate::{ArcCell, ArcCellInner, ArcRef};
+
+/// A cell that drops swapped-out values on a dedicated thread, see
+/// [`ArcCell::with_background_reclaim`].
+pub struct BackgroundCell<T> {
+    cell: ArcCell<T>,
+    dropper: Arc<Dropper<T>>,
+}
+
+/// The thread a [`BackgroundCell`] sends its old values to.
+///
+/// Dropping it closes the channel and joins the thread, so everything sent
+/// has been dropped by then.
+struct Dropper<T> {
+    sender: Option<mpsc::Sender<Box<T>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> Dropper<T> {
+    fn spawn() -> Dropper<T> {
+        let (sender, receiver) = mpsc::channel::<Box<T>>();
+        let thread = thread::spawn(move || receiver.into_iter().for_each(drop));
+
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<T> Dropper<T> {
+    #[inline]
+    fn send(&self, data: Box<T>) {
+        if let Some(sender) = &self.sender {
+            // The receiver only goes away if a destructor panicked on the
+            // dropper thread; drop here instead.
+            if let Err(mpsc::SendError(data)) = sender.send(data) {
+                drop(data);
+            }
+        }
+    }
+}
+
+impl<T> Drop for Dropper<T> {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<T> BackgroundCell<T> {
+    #[inline]
+    pub fn get(&self) -> ArcRef<T> {
+        self.cell.get()
+    }
+
+    /// Installs `data`. The old value is sent to the dropper thread once no
+    /// reader can still be using it, and waits in the cell until then.
+    pub fn set(&self, data: Box<T>) {
+        let old = self.cell.inner().set_ptr(Box::into_raw(data));
+
+        self.cell.inner().retire(ArcCellInner::ptr_of(old), None);
+        self.cell.inner().reclaim(|data| self.dropper.send(data));
+    }
+}
+
+impl<T> Clone for BackgroundCell<T> {
+    fn clone(&self) -> Self {
+        Self {
+            cell: self.cell.clone(),
+            dropper: Arc::clone(&self.dropper),
+        }
+    }
+}
+
+impl<T: Send + 'static> ArcCell<T> {
+    /// Creates a cell whose [`set`](BackgroundCell::set) drops the old value
+    /// on a dedicated thread, keeping destructor cost off the writer.
+    ///
+    /// The thread is stopped and joined when the last `BackgroundCell` handle
+    /// drops, after it has dropped everything sent to it. The current value,
+    /// and any old values still waiting for readers, are dropped by whichever
+    /// handle or reader is the last to let go of the cell.
+    pub fn with_background_reclaim(data: Box<T>) -> BackgroundCell<T> {
+        BackgroundCell {
+            cell: ArcCell::new(data),
+            dropper: Arc::new(Dropper::spawn()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+
+    struct Slow(Arc<AtomicUsize>);
+
+    impl Drop for Slow {
+        fn drop(&mut self) {
+            thread::sleep(Duration::from_millis(50));
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn writer_not_blocked_by_drop() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let v = ArcCell::with_background_reclaim(Box::new(Slow(Arc::clone(&dropped))));
+
+        let start = Instant::now();
+        for _ in 0..10 {
+            v.set(Box::new(Slow(Arc::clone(&dropped))));
+        }
+        assert!(start.elapsed() < Duration::from_millis(250));
+
+        drop(v);
+        assert_eq!(dropped.load(Ordering::SeqCst), 11);
+    }
+
+    #[test]
+    fn held_value_outlives_cell_handles() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let v = ArcCell::with_background_reclaim(Box::new(Slow(Arc::clone(&dropped))));
+
+        let reader = v.get();
+        v.set(Box::new(Slow(Arc::clone(&dropped))));
+        drop(v);
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+
+        drop(reader);
+        assert_eq!(dropped.load(Ordering::SeqCst), 2);
+    }
+}
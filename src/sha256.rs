@@ -0,0 +1,84 @@
+use std::hash::Hasher;
+
+use sha2::Digest;
+
+/// SHA-256 from the `sha2` crate as a `Hasher`, for hashes that must come
+/// out the same on every build and platform.
+///
+/// Integers are written little-endian and `usize`/`isize` are widened to 64
+/// bits, so a value's bytes do not depend on the target.
+#[derive(Clone, Default)]
+pub(crate) struct Sha256(sha2::Sha256);
+
+impl Sha256 {
+    /// The digest of `write`'s output
+    #[inline]
+    pub(crate) fn digest<F: FnOnce(&mut Sha256)>(write: F) -> [u8; 32] {
+        let mut hasher = Sha256::default();
+        write(&mut hasher);
+        hasher.0.finalize().into()
+    }
+
+    #[inline]
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
+
+impl Hasher for Sha256 {
+    /// The first eight bytes of the digest so far, little-endian
+    fn finish(&self) -> u64 {
+        let mut first = [0; 8];
+        first.copy_from_slice(&self.0.clone().finalize()[..8]);
+        u64::from_le_bytes(first)
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+
+    #[inline]
+    fn write_u16(&mut self, i: u16) {
+        self.update(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u32(&mut self, i: u32) {
+        self.update(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u64(&mut self, i: u64) {
+        self.update(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_u128(&mut self, i: u128) {
+        self.update(&i.to_le_bytes());
+    }
+
+    #[inline]
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    #[inline]
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as i64 as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_written_portably() {
+        let digest = |write: fn(&mut Sha256)| Sha256::digest(write);
+
+        assert_eq!(digest(|h| h.write_u32(1)), digest(|h| h.update(&[1, 0, 0, 0])));
+        assert_eq!(digest(|h| h.write_usize(1)), digest(|h| h.write_u64(1)));
+        assert_eq!(digest(|h| h.write_isize(-1)), digest(|h| h.write_u64(u64::MAX)));
+    }
+}
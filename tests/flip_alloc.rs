@@ -0,0 +1,57 @@
+//! Kept in its own test binary, since the counting allocator replaces the
+//! global allocator for every test in the binary it is part of.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::Arc;
+
+use arc_cell::Flip;
+
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+#[test]
+fn flip_does_not_allocate() {
+    let flip = Arc::new(Flip::new(Box::new(false), Box::new(true)));
+
+    let readers = (0..4)
+        .map(|_| {
+            let flip = Arc::clone(&flip);
+            std::thread::spawn(move || {
+                for _ in 0..1000 {
+                    std::hint::black_box(*flip.get());
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let before = ALLOCATIONS.with(Cell::get);
+    let mut last = *flip.get();
+    for _ in 0..10_000 {
+        let value = *flip.flip();
+        assert_ne!(value, last);
+        last = value;
+    }
+    assert_eq!(ALLOCATIONS.with(Cell::get), before);
+
+    for reader in readers {
+        reader.join().unwrap();
+    }
+}